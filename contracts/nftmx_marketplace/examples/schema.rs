@@ -4,7 +4,9 @@ use std::fs::create_dir_all;
 use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
 
 use nftmx_marketplace::{
-  ExecuteMsg, InstantiateMsg, QueryMsg
+  ExecuteMsg, InstantiateMsg, QueryMsg, SettlementResponse, AcceptedAssetsResponse, OrdersResponse, NegotiationResponse, SalesResponse,
+  CollectionStatsResponse, TopCollectionsResponse, TwapResponse, UserStatsResponse, LeaderboardResponse, OrderResponse,
+  PauseStatusResponse, EscrowBalancesResponse, ClaimableResponse, AccruedFeesResponse, FeeScheduleResponse
 };
 
 fn main() {
@@ -16,4 +18,20 @@ fn main() {
   export_schema(&schema_for!(InstantiateMsg), &out_dir);
   export_schema(&schema_for!(ExecuteMsg), &out_dir);
   export_schema(&schema_for!(QueryMsg), &out_dir);
+  export_schema(&schema_for!(SettlementResponse), &out_dir);
+  export_schema(&schema_for!(AcceptedAssetsResponse), &out_dir);
+  export_schema(&schema_for!(OrderResponse), &out_dir);
+  export_schema(&schema_for!(OrdersResponse), &out_dir);
+  export_schema(&schema_for!(NegotiationResponse), &out_dir);
+  export_schema(&schema_for!(SalesResponse), &out_dir);
+  export_schema(&schema_for!(CollectionStatsResponse), &out_dir);
+  export_schema(&schema_for!(TopCollectionsResponse), &out_dir);
+  export_schema(&schema_for!(TwapResponse), &out_dir);
+  export_schema(&schema_for!(UserStatsResponse), &out_dir);
+  export_schema(&schema_for!(LeaderboardResponse), &out_dir);
+  export_schema(&schema_for!(PauseStatusResponse), &out_dir);
+  export_schema(&schema_for!(EscrowBalancesResponse), &out_dir);
+  export_schema(&schema_for!(ClaimableResponse), &out_dir);
+  export_schema(&schema_for!(AccruedFeesResponse), &out_dir);
+  export_schema(&schema_for!(FeeScheduleResponse), &out_dir);
 }
@@ -5,6 +5,7 @@ use cosmwasm_std::{to_binary, Addr, BankMsg, Coin, CosmosMsg, Decimal, MessageIn
   Uint128, WasmMsg};
 use cw20::{Cw20ExecuteMsg};
 use terra_cosmwasm::TerraQuerier;
+use crate::error::ContractError;
 
 static DECIMAL_FRACTION: Uint128 = Uint128::new(1_000_000_000_000_000_000u128);
 
@@ -76,7 +77,7 @@ impl Asset{
     if let AssetInfo::NativeToken { denom } = &self.info {
       match message_info.funds.iter().find(|x| x.denom == *denom) {
         Some(coin) => {
-          if self.amount < coin.amount {
+          if self.amount == coin.amount {
             Ok(())
           } else {
             Err(StdError::generic_err("Native token balance mismatch between the argument and the transferred"))
@@ -95,7 +96,23 @@ impl Asset{
     }
   }
 
-  pub fn into_msg(self, querier: &QuerierWrapper, recipient: Addr) -> StdResult<CosmosMsg> {
+  /// Transfer message that deducts the Terra tax from the amount, so the contract's
+  /// balance decreases by exactly `self.amount`.
+  pub fn into_msg<T>(self, querier: &QuerierWrapper, recipient: Addr) -> Result<CosmosMsg<T>, ContractError>
+    where T: Clone + fmt::Debug + PartialEq + JsonSchema
+  {
+    let tax = self.compute_tax(querier)?;
+    Asset {
+      info: self.info,
+      amount: self.amount.checked_sub(tax)?
+    }.into_send_msg(recipient)
+  }
+
+  /// Transfer message for exactly `self.amount`. For native tokens the tax is charged
+  /// on top, so the caller must make sure the contract holds `amount + compute_tax_on_top`.
+  pub fn into_send_msg<T>(self, recipient: Addr) -> Result<CosmosMsg<T>, ContractError>
+    where T: Clone + fmt::Debug + PartialEq + JsonSchema
+  {
     let amount = self.amount;
 
     match &self.info {
@@ -107,14 +124,14 @@ impl Asset{
         })?,
         funds: vec![],
       })),
-      AssetInfo::NativeToken { .. } => Ok(CosmosMsg::Bank(BankMsg::Send {
+      AssetInfo::NativeToken { denom } => Ok(CosmosMsg::Bank(BankMsg::Send {
         to_address: recipient.to_string(),
-        amount: vec![self.deduct_tax(querier)?],
+        amount: vec![Coin { denom: denom.to_string(), amount }],
       })),
     }
   }
 
-  pub fn deduct_tax(&self, querier: &QuerierWrapper) -> StdResult<Coin> {
+  pub fn deduct_tax(&self, querier: &QuerierWrapper) -> Result<Coin, ContractError> {
     let amount = self.amount;
     if let AssetInfo::NativeToken { denom } = &self.info {
       Ok(Coin {
@@ -122,29 +139,47 @@ impl Asset{
         amount: amount.checked_sub(self.compute_tax(querier)?)?,
       })
     } else {
-      Err(StdError::generic_err("cannot deduct tax from token asset"))
+      Err(StdError::generic_err("cannot deduct tax from token asset").into())
     }
   }
 
-  pub fn compute_tax(&self, querier: &QuerierWrapper) -> StdResult<Uint128> {
+  /// Tax included in `self.amount`: sending `amount - tax` costs the sender `amount` in total.
+  pub fn compute_tax(&self, querier: &QuerierWrapper) -> Result<Uint128, ContractError> {
     let amount = self.amount;
-    if let AssetInfo::NativeToken { denom } = &self.info {
-      if denom == "uluna" {
-        Ok(Uint128::zero())
-      } else {
-        let terra_querier = TerraQuerier::new(querier);
-        let tax_rate: Decimal = (terra_querier.query_tax_rate()?).rate;
-        let tax_cap: Uint128 = (terra_querier.query_tax_cap(denom.to_string())?).cap;
-        Ok(std::cmp::min(
-          amount.checked_sub(amount.multiply_ratio(
-            DECIMAL_FRACTION,
-            DECIMAL_FRACTION * tax_rate + DECIMAL_FRACTION,
-          ))?,
-          tax_cap,
-        ))
-      }
-    } else {
-      Ok(Uint128::zero())
+    match query_tax_rate_and_cap(querier, &self.info)? {
+      Some((tax_rate, tax_cap)) => Ok(std::cmp::min(
+        amount.checked_sub(amount.multiply_ratio(
+          DECIMAL_FRACTION,
+          DECIMAL_FRACTION * tax_rate + DECIMAL_FRACTION,
+        ))?,
+        tax_cap,
+      )),
+      None => Ok(Uint128::zero()),
+    }
+  }
+
+  /// Tax charged on top of `self.amount` when exactly that amount is sent.
+  pub fn compute_tax_on_top(&self, querier: &QuerierWrapper) -> Result<Uint128, ContractError> {
+    match query_tax_rate_and_cap(querier, &self.info)? {
+      Some((tax_rate, tax_cap)) => Ok(std::cmp::min(self.amount * tax_rate, tax_cap)),
+      None => Ok(Uint128::zero()),
     }
   }
-}
\ No newline at end of file
+}
+
+/// Terra tax rate and cap for a taxable native denom, `None` for luna and cw20 tokens
+fn query_tax_rate_and_cap(querier: &QuerierWrapper, info: &AssetInfo) -> Result<Option<(Decimal, Uint128)>, ContractError> {
+  match info {
+    AssetInfo::NativeToken { denom } if denom != "uluna" => {
+      let tax_err = |e: StdError| ContractError::TaxComputationFailed {
+        denom: denom.to_string(),
+        reason: e.to_string()
+      };
+      let terra_querier = TerraQuerier::new(querier);
+      let tax_rate: Decimal = terra_querier.query_tax_rate().map_err(tax_err)?.rate;
+      let tax_cap: Uint128 = terra_querier.query_tax_cap(denom.to_string()).map_err(tax_err)?.cap;
+      Ok(Some((tax_rate, tax_cap)))
+    }
+    _ => Ok(None),
+  }
+}
@@ -4,14 +4,31 @@ use cosmwasm_std::entry_point;
 
 use cosmwasm_std::{
     to_binary, DepsMut, Env, MessageInfo, CosmosMsg, Response, QueryRequest, WasmMsg, WasmQuery, StdResult, Deps, Binary, Uint128,
-    Storage, QuerierWrapper, Decimal
+    Storage, QuerierWrapper, Decimal, Addr, StdError, Coin, Order as CwOrder, BlockInfo
 };
+use cw0::Expiration;
+use cw2::{ get_contract_version, set_contract_version };
+use std::collections::BTreeMap;
 use cw721::{Cw721ExecuteMsg, Cw721QueryMsg, OwnerOfResponse};
-use cw0:: Expiration;
+use cw20::{ BalanceResponse as Cw20BalanceResponse, Cw20QueryMsg };
+use terra_cosmwasm::{ create_swap_send_msg, TerraMsgWrapper, TerraQuerier };
 
-use crate::state::{ ORDERS, Order, BIDS, Bid, Config, CONFIG };
-use crate::msg::{ ExecuteMsg, InstantiateMsg, QueryMsg, MigrateMsg };
-use crate::asset::{ Asset };
+use crate::state::{ orders, Order, bids, Bid, COUNTER_OFFERS, CounterOffer, NEGOTIATIONS, NegotiationEntry, NegotiationAction, Config, CONFIG, PayoutSwap, ACCEPTED_ASSETS, COLLECTION_ACCEPTED_ASSETS,
+    Bundle, BundleItem, BUNDLES, BUNDLE_COUNT, Swap, SwapWant, SWAPS, SWAP_COUNT, ListingDurations, EXPIRING_KINDS,
+    LEGACY_ORDERS, LEGACY_BIDS, sales, Sale, SaleType, SALE_COUNT, COLLECTION_STATS, collection_volumes, CollectionVolume,
+    PriceObservation, PRICE_OBSERVATIONS, USER_STATS, user_volumes, UserVolume, COLLECTIONS, CollectionInfo, CollectionStatus,
+    Delisting, DELISTINGS, BLOCKED_TOKENS, BLOCKED_ADDRESSES, Operation, COLLECTION_PAUSES, LEGACY_PAUSE,
+    ESCROW, ACCRUED_FEES, PayoutMode, PAYOUT_MODES, CLAIMABLE, FeeTier, COLLECTION_FEES };
+use crate::msg::{ ExecuteMsg, InstantiateMsg, QueryMsg, MigrateMsg, SettlementResponse, AcceptedAssetsResponse, NftItemMsg,
+    OrderMsg, BatchMode, BuyMsg, OrdersResponse, SwapWantMsg, NegotiationResponse, SaleFilter, SalesResponse,
+    CollectionStatsResponse, TopCollectionsResponse, TwapResponse, TwapPrice,
+    UserStatsResponse, LeaderboardResponse, OrderResponse, PauseStatusResponse,
+    EscrowBalancesResponse, EscrowBalance, ClaimableResponse, AccruedFeesResponse, FeeScheduleResponse };
+use cw_storage_plus::{ Bound, Map, PrimaryKey, U64Key, U128Key };
+use crate::asset::{ Asset, AssetInfo };
+
+const CONTRACT_NAME: &str = "crates.io:nftmx-marketplace";
+const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 #[entry_point]
 pub fn instantiate(
@@ -19,15 +36,26 @@ pub fn instantiate(
     _env: Env,
     info: MessageInfo,
     msg: InstantiateMsg,
-) -> StdResult<Response> {
+) -> Result<Response, ContractError> {
     let con = Config {
         owner: info.sender,
-        accepted_token: deps.api.addr_validate(&msg.accepted_token)?,
         owner_cut_rate: msg.owner_cut_rate,
         owner_cut_rate_max: Decimal::percent(10),
-        paused: false
+        buyer_pays_tax: msg.buyer_pays_tax,
+        paused_operations: 0,
+        emergency: false,
+        withdrawal_mode: false,
+        keeper_fee: Decimal::zero(),
+        listing_durations: ListingDurations::default(),
+        curators: vec![],
+        require_verified: false,
+        moderators: vec![],
+        fee_tiers: vec![]
     };
     CONFIG.save(deps.storage, &con)?;
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+    let accepted_assets = validate_asset_infos(msg.accepted_assets)?;
+    ACCEPTED_ASSETS.save(deps.storage, &accepted_assets)?;
     Ok(Response::default())
 }
 
@@ -37,21 +65,56 @@ pub fn execute(
     env: Env,
     info: MessageInfo,
     msg: ExecuteMsg,
-) -> Result<Response, ContractError> {
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
     match msg {
-        ExecuteMsg::SetPaused { paused } => set_paused(deps, env, info, paused),
-        ExecuteMsg::CreateOrder{ token_id, nft_address, price, expire_at } => create_order(deps, env, info, token_id, nft_address, price, expire_at),
-        ExecuteMsg::UpdateOrder{ token_id, nft_address, price, expire_at } => update_order(deps, env, info, token_id, nft_address, price, expire_at),
+        ExecuteMsg::SetPaused { operations } => set_paused(deps, env, info, operations),
+        ExecuteMsg::SetCollectionPaused { nft_address, operations } => set_collection_paused(deps, env, info, nft_address, operations),
+        ExecuteMsg::SetEmergency { emergency } => set_emergency(deps, env, info, emergency),
+        ExecuteMsg::SetWithdrawalMode { enabled } => set_withdrawal_mode(deps, env, info, enabled),
+        ExecuteMsg::EmergencyWithdraw { limit } => emergency_withdraw(deps, env, info, limit),
+        ExecuteMsg::SetKeeperFee { keeper_fee } => set_keeper_fee(deps, env, info, keeper_fee),
+        ExecuteMsg::ClaimFees { recipient, assets } => claim_fees(deps, env, info, recipient, assets),
+        ExecuteMsg::SetListingDurations { durations } => set_listing_durations(deps, env, info, durations),
+        ExecuteMsg::SetCurators { curators } => set_curators(deps, env, info, curators),
+        ExecuteMsg::SetRequireVerified { require_verified } => set_require_verified(deps, env, info, require_verified),
+        ExecuteMsg::SetCollection { nft_address, name, creator, royalty_rate, status } => set_collection(deps, env, info, nft_address, name, creator, royalty_rate, status),
+        ExecuteMsg::RemoveCollection { nft_address } => remove_collection(deps, env, info, nft_address),
+        ExecuteMsg::SetModerators { moderators } => set_moderators(deps, env, info, moderators),
+        ExecuteMsg::ForceCancelOrder { token_id, nft_address, recovery_address, reason } => force_cancel_order(deps, env, info, token_id, nft_address, recovery_address, reason),
+        ExecuteMsg::SetTokenBlocked { token_id, nft_address, blocked } => set_token_blocked(deps, env, info, token_id, nft_address, blocked),
+        ExecuteMsg::SetAddressBlocked { address, blocked } => set_address_blocked(deps, env, info, address, blocked),
+        ExecuteMsg::CleanupExpired { limit } => cleanup_expired(deps, env, info, limit),
+        ExecuteMsg::UpdateAcceptedAssets { assets } => update_accepted_assets(deps, env, info, assets),
+        ExecuteMsg::SetCollectionAcceptedAssets { nft_address, assets } => set_collection_accepted_assets(deps, env, info, nft_address, assets),
+        ExecuteMsg::SetCollectionFee { nft_address, rate } => set_collection_fee(deps, env, info, nft_address, rate),
+        ExecuteMsg::SetFeeTiers { tiers } => set_fee_tiers(deps, env, info, tiers),
+        ExecuteMsg::CreateOrder{ token_id, nft_address, price, expire_at, payout_swap, reserved_for } => create_order(deps, env, info, token_id, nft_address, price, expire_at, payout_swap, reserved_for),
+        ExecuteMsg::UpdateOrder{ token_id, nft_address, price, expire_at, payout_swap, reserved_for } => update_order(deps, env, info, token_id, nft_address, price, expire_at, payout_swap, reserved_for),
         ExecuteMsg::CreateBid{ token_id, nft_address, price, expire_at } => create_bid(deps, env, info, token_id, nft_address, price, expire_at),
         ExecuteMsg::CancelOrder{ token_id, nft_address } => cancel_order(deps, env, info, token_id, nft_address),
         ExecuteMsg::CancelBid{ token_id, nft_address } => cancel_bid(deps, env, info, token_id, nft_address),
         ExecuteMsg::SafeExecuteOrder{ token_id, nft_address, price } => safe_execute_order(deps, env, info, token_id, nft_address, price),
-        ExecuteMsg::AcceptBid{ token_id, nft_address, price } => accept_bid(deps, env, info, token_id, nft_address, price)
+        ExecuteMsg::AcceptBid{ token_id, nft_address, price } => accept_bid(deps, env, info, token_id, nft_address, price),
+        ExecuteMsg::CounterOffer{ token_id, nft_address, bidder, price, expire_at } => counter_offer(deps, env, info, token_id, nft_address, bidder, price, expire_at),
+        ExecuteMsg::AcceptCounterOffer{ token_id, nft_address, price } => accept_counter_offer(deps, env, info, token_id, nft_address, price),
+        ExecuteMsg::CreateBundle{ nfts, price, expire_at, payout_swap } => create_bundle(deps, env, info, nfts, price, expire_at, payout_swap),
+        ExecuteMsg::CancelBundle{ bundle_id } => cancel_bundle(deps, env, info, bundle_id),
+        ExecuteMsg::BuyBundle{ bundle_id, price } => buy_bundle(deps, env, info, bundle_id, price),
+        ExecuteMsg::BatchCreateOrders{ orders, mode } => batch_create_orders(deps, env, info, orders, mode),
+        ExecuteMsg::BatchUpdateOrders{ orders, mode } => batch_update_orders(deps, env, info, orders, mode),
+        ExecuteMsg::BatchCancelOrders{ orders, mode } => batch_cancel_orders(deps, env, info, orders, mode),
+        ExecuteMsg::BatchBuy{ orders } => batch_buy(deps, env, info, orders),
+        ExecuteMsg::CreateSwap{ offered, sweetener, wanted, expire_at } => create_swap(deps, env, info, offered, sweetener, wanted, expire_at),
+        ExecuteMsg::CancelSwap{ swap_id } => cancel_swap(deps, env, info, swap_id),
+        ExecuteMsg::FillSwap{ swap_id, nfts } => fill_swap(deps, env, info, swap_id, nfts),
+        ExecuteMsg::SweepFloor{ nft_address, max_count, max_total_price } => sweep_floor(deps, env, info, nft_address, max_count, max_total_price),
+        ExecuteMsg::SetPayoutMode { mode } => set_payout_mode(deps, env, info, mode),
+        ExecuteMsg::Withdraw { assets } => withdraw(deps, env, info, assets)
     }
 }
 
 #[entry_point]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
         QueryMsg::Version {} => {
             // let seconds = env.block.time;
@@ -59,471 +122,1631 @@ pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
         }
 
         QueryMsg::GetOrder { token_id, nft_address } => {
-            let order = ORDERS.load(deps.storage, (&token_id, &nft_address))?;
-            to_binary(&order)
+            let order = orders().load(deps.storage, (&token_id, &nft_address))?;
+            to_binary(&order_response(deps.storage, order)?)
         }
         QueryMsg::GetBid { token_id, nft_address } => {
-            let bid = BIDS.load(deps.storage, (&token_id, &nft_address))?;
+            let bid = bids().load(deps.storage, (&token_id, &nft_address))?;
             to_binary(&bid)
         },
         QueryMsg::GetNftOwner { token_id, nft_address } => {
             let owner_res: OwnerOfResponse =
             deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
-                contract_addr: nft_address.clone(),
-                msg: to_binary(&Cw721QueryMsg::OwnerOf { token_id: token_id.clone(), include_expired: Some(false) })?,
-            })).unwrap();
+                contract_addr: nft_address,
+                msg: to_binary(&Cw721QueryMsg::OwnerOf { token_id, include_expired: Some(false) })?,
+            }))?;
             to_binary(&owner_res)
         }
+        QueryMsg::SimulateBuy { token_id, nft_address } => {
+            let con = CONFIG.load(deps.storage)?;
+            let order = orders().load(deps.storage, (&token_id, &nft_address))?;
+            let royalties = collection_royalties(deps.storage, &[&order.nft_address], &order.price)?;
+            let settlement = compute_settlement(&deps.querier, fee_rate(deps.storage, &con, &[&order.nft_address], &order.seller, &order.price.info)?, &order.price, &order.seller, royalties, order.payout_swap.as_ref(), con.buyer_pays_tax)
+                .map_err(|e| StdError::generic_err(e.to_string()))?;
+            to_binary(&settlement.breakdown)
+        }
+        QueryMsg::SimulateAcceptBid { token_id, nft_address } => {
+            let con = CONFIG.load(deps.storage)?;
+            let order = orders().load(deps.storage, (&token_id, &nft_address))?;
+            let bid = bids().load(deps.storage, (&token_id, &nft_address))?;
+            let royalties = collection_royalties(deps.storage, &[&order.nft_address], &bid.price)?;
+            let settlement = compute_settlement(&deps.querier, fee_rate(deps.storage, &con, &[&order.nft_address], &bid.seller, &bid.price.info)?, &bid.price, &bid.seller, royalties, order.payout_swap.as_ref(), false)
+                .map_err(|e| StdError::generic_err(e.to_string()))?;
+            to_binary(&settlement.breakdown)
+        }
+        QueryMsg::GetCounterOffer { token_id, nft_address } => {
+            let counter_offer = COUNTER_OFFERS.load(deps.storage, (&token_id, &nft_address))?;
+            to_binary(&counter_offer)
+        }
+        QueryMsg::GetNegotiation { token_id, nft_address } => {
+            let entries = NEGOTIATIONS.may_load(deps.storage, (&token_id, &nft_address))?.unwrap_or_default();
+            to_binary(&NegotiationResponse { entries })
+        }
+        QueryMsg::AcceptedAssets { nft_address } => {
+            let assets = match nft_address {
+                Some(nft_address) => accepted_assets(deps.storage, &nft_address)?,
+                None => ACCEPTED_ASSETS.load(deps.storage)?
+            };
+            to_binary(&AcceptedAssetsResponse { assets })
+        }
+        QueryMsg::FeeSchedule { nft_address } => {
+            let con = CONFIG.load(deps.storage)?;
+            let collection_rate = match nft_address {
+                Some(nft_address) => COLLECTION_FEES.may_load(deps.storage, &nft_address)?,
+                None => None
+            };
+            to_binary(&FeeScheduleResponse {
+                owner_cut_rate: con.owner_cut_rate,
+                owner_cut_rate_max: con.owner_cut_rate_max,
+                collection_rate,
+                tiers: con.fee_tiers
+            })
+        }
+        QueryMsg::ListOrders { nft_address, viewer, start_after, limit } => {
+            to_binary(&query_list_orders(deps, env, nft_address, viewer, start_after, limit)?)
+        }
+        QueryMsg::GetBundle { bundle_id } => {
+            let bundle = BUNDLES.load(deps.storage, bundle_id.into())?;
+            to_binary(&bundle)
+        }
+        QueryMsg::GetSale { sale_id } => {
+            let sale = sales().load(deps.storage, sale_id.into())?;
+            to_binary(&sale)
+        }
+        QueryMsg::Sales { filter, start_after, limit } => {
+            to_binary(&query_sales(deps, filter, start_after, limit)?)
+        }
+        QueryMsg::PauseStatus { nft_address } => {
+            let con = CONFIG.load(deps.storage)?;
+            let mut bits = con.paused_operations;
+            if let Some(nft_address) = nft_address {
+                bits |= COLLECTION_PAUSES.may_load(deps.storage, &nft_address)?.unwrap_or_default();
+            }
+            to_binary(&PauseStatusResponse { paused: Operation::from_bits(bits), emergency: con.emergency })
+        }
+        QueryMsg::EscrowBalances {} => {
+            to_binary(&query_escrow_balances(deps, env)?)
+        }
+        QueryMsg::AccruedFees {} => {
+            to_binary(&query_accrued_fees(deps)?)
+        }
+        QueryMsg::Claimable { address } => {
+            to_binary(&query_claimable(deps, address)?)
+        }
+        QueryMsg::GetCollection { nft_address } => {
+            let collection = COLLECTIONS.load(deps.storage, &nft_address)?;
+            to_binary(&collection)
+        }
+        QueryMsg::GetDelisting { token_id, nft_address } => {
+            let delisting = DELISTINGS.load(deps.storage, (&token_id, &nft_address))?;
+            to_binary(&delisting)
+        }
+        QueryMsg::IsTokenBlocked { token_id, nft_address } => {
+            to_binary(&BLOCKED_TOKENS.has(deps.storage, (&token_id, &nft_address)))
+        }
+        QueryMsg::IsAddressBlocked { address } => {
+            to_binary(&BLOCKED_ADDRESSES.has(deps.storage, &address))
+        }
+        QueryMsg::CollectionStats { nft_address } => {
+            to_binary(&query_collection_stats(deps, env, nft_address)?)
+        }
+        QueryMsg::TopCollections { asset_info, start_after, limit } => {
+            to_binary(&query_top_collections(deps, asset_info, start_after, limit)?)
+        }
+        QueryMsg::UserStats { address } => {
+            to_binary(&query_user_stats(deps, address)?)
+        }
+        QueryMsg::Leaderboard { asset_info, start_after, limit } => {
+            to_binary(&query_leaderboard(deps, asset_info, start_after, limit)?)
+        }
+        QueryMsg::Twap { nft_address, window_seconds } => {
+            to_binary(&query_twap(deps, env, nft_address, window_seconds)?)
+        }
+        QueryMsg::GetSwap { swap_id } => {
+            let swap = SWAPS.load(deps.storage, swap_id.into())?;
+            to_binary(&swap)
+        }
+        QueryMsg::SimulateBuyBundle { bundle_id } => {
+            let con = CONFIG.load(deps.storage)?;
+            let bundle = BUNDLES.load(deps.storage, bundle_id.into())?;
+            let royalties = bundle_royalties(deps.storage, &bundle)?;
+            let settlement = compute_settlement(&deps.querier, bundle_fee_rate(deps.storage, &con, &bundle)?, &bundle.price, &bundle.seller, royalties, bundle.payout_swap.as_ref(), con.buyer_pays_tax)
+                .map_err(|e| StdError::generic_err(e.to_string()))?;
+            to_binary(&settlement.breakdown)
+        }
+    }
+}
+
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 30;
+
+fn query_list_orders(
+    deps: Deps,
+    env: Env,
+    nft_address: Option<String>,
+    viewer: Option<String>,
+    start_after: Option<NftItemMsg>,
+    limit: Option<u32>
+) -> StdResult<OrdersResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let viewer = viewer.map(|v| deps.api.addr_validate(&v)).transpose()?;
+    let start = start_after.map(|s| Bound::exclusive((s.token_id.as_str(), s.nft_address.as_str()).joined_key()));
+    // a collection only walks its own orders, through the collection index
+    let listed: Box<dyn Iterator<Item = StdResult<(Vec<u8>, Order)>>> = match &nft_address {
+        Some(nft_address) => orders().idx.collection
+            .prefix(nft_address.as_bytes().to_vec())
+            .range(deps.storage, start, None, CwOrder::Ascending),
+        None => orders().range(deps.storage, start, None, CwOrder::Ascending)
+    };
+    let orders = listed
+        .map(|item| item.map(|(_, order)| order))
+        .filter(|item| match item {
+            Ok(order) => {
+                let visible = match &order.reserved_for {
+                    Some(reserved_for) => matches!(&viewer, Some(v) if v == reserved_for || *v == order.seller),
+                    None => true
+                };
+                visible && !order.expire_at.is_expired(&env.block)
+            }
+            Err(_) => true
+        })
+        .take(limit)
+        .map(|item| item.and_then(|order| order_response(deps.storage, order)))
+        .collect::<StdResult<Vec<_>>>()?;
+    Ok(OrdersResponse { orders })
+}
+
+fn order_response(storage: &dyn Storage, order: Order) -> StdResult<OrderResponse> {
+    let collection = COLLECTIONS.may_load(storage, order.nft_address.as_str())?;
+    let verified = matches!(&collection, Some(c) if c.status == CollectionStatus::Verified);
+    Ok(OrderResponse { order, collection, verified })
+}
+
+/// Listings looked at per asset for a floor price, cheapest first
+const MAX_FLOOR_SCAN: usize = 100;
+
+fn query_collection_stats(deps: Deps, env: Env, nft_address: String) -> StdResult<CollectionStatsResponse> {
+    let nft_address = deps.api.addr_validate(&nft_address)?;
+    let stats = COLLECTION_STATS.may_load(deps.storage, nft_address.as_str())?.unwrap_or_default();
+    let volume = collection_volumes()
+        .prefix(nft_address.as_bytes())
+        .range(deps.storage, None, None, CwOrder::Ascending)
+        .map(|item| item.map(|(_, v)| v.volume))
+        .collect::<StdResult<Vec<_>>>()?;
+    let mut floor = vec![];
+    for info in accepted_assets(deps.storage, nft_address.as_str())? {
+        let cheapest = orders().idx.price
+            .sub_prefix((nft_address.as_bytes().to_vec(), info.as_bytes().to_vec()))
+            .range(deps.storage, None, None, CwOrder::Ascending)
+            .take(MAX_FLOOR_SCAN)
+            .map(|item| item.map(|(_, order)| order))
+            .find(|item| item.as_ref().map_or(true, |order| order.reserved_for.is_none() && !order.expire_at.is_expired(&env.block)))
+            .transpose()?;
+        if let Some(order) = cheapest {
+            floor.push(order.price);
+        }
+    }
+    Ok(CollectionStatsResponse {
+        nft_address: nft_address.to_string(),
+        volume,
+        sales_count: stats.sales_count,
+        last_sale: stats.last_sale,
+        floor,
+        listed_count: stats.listed_count
+    })
+}
+
+fn query_top_collections(
+    deps: Deps,
+    asset_info: AssetInfo,
+    start_after: Option<String>,
+    limit: Option<u32>
+) -> StdResult<TopCollectionsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let end = match start_after {
+        Some(nft_address) => {
+            let nft_address = deps.api.addr_validate(&nft_address)?;
+            let pk = (nft_address.as_bytes(), asset_info.as_bytes());
+            let last = collection_volumes().load(deps.storage, pk)?;
+            Some(Bound::exclusive((U128Key::new(last.volume.amount.u128()), pk.joined_key()).joined_key()))
+        }
+        None => None
+    };
+    let collections = collection_volumes().idx.volume
+        .sub_prefix(asset_info.as_bytes().to_vec())
+        .range(deps.storage, None, end, CwOrder::Descending)
+        .take(limit)
+        .map(|item| item.map(|(_, v)| v))
+        .collect::<StdResult<Vec<_>>>()?;
+    Ok(TopCollectionsResponse { collections })
+}
+
+fn query_user_stats(deps: Deps, address: String) -> StdResult<UserStatsResponse> {
+    let address = deps.api.addr_validate(&address)?;
+    let stats = USER_STATS.may_load(deps.storage, address.as_str())?.unwrap_or_default();
+    let volumes = user_volumes()
+        .prefix(address.as_bytes())
+        .range(deps.storage, None, None, CwOrder::Ascending)
+        .map(|item| item.map(|(_, v)| v))
+        .collect::<StdResult<Vec<_>>>()?;
+    Ok(UserStatsResponse {
+        address: address.to_string(),
+        items_bought: stats.items_bought,
+        items_sold: stats.items_sold,
+        volumes
+    })
+}
+
+fn query_leaderboard(
+    deps: Deps,
+    asset_info: AssetInfo,
+    start_after: Option<String>,
+    limit: Option<u32>
+) -> StdResult<LeaderboardResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let end = match start_after {
+        Some(address) => {
+            let address = deps.api.addr_validate(&address)?;
+            let pk = (address.as_bytes(), asset_info.as_bytes());
+            let last = user_volumes().load(deps.storage, pk)?;
+            Some(Bound::exclusive((U128Key::new(last.bought.u128().saturating_add(last.sold.u128())), pk.joined_key()).joined_key()))
+        }
+        None => None
+    };
+    let entries = user_volumes().idx.volume
+        .sub_prefix(asset_info.as_bytes().to_vec())
+        .range(deps.storage, None, end, CwOrder::Descending)
+        .take(limit)
+        .map(|item| item.map(|(_, v)| v))
+        .collect::<StdResult<Vec<_>>>()?;
+    Ok(LeaderboardResponse { entries })
+}
+
+fn query_twap(deps: Deps, env: Env, nft_address: String, window_seconds: u64) -> StdResult<TwapResponse> {
+    let nft_address = deps.api.addr_validate(&nft_address)?;
+    let now = env.block.time.seconds();
+    let start = now.saturating_sub(window_seconds);
+    let assets = collection_volumes()
+        .prefix(nft_address.as_bytes())
+        .range(deps.storage, None, None, CwOrder::Ascending)
+        .map(|item| item.map(|(_, v)| v.volume.info))
+        .collect::<StdResult<Vec<_>>>()?;
+    let mut prices = vec![];
+    for info in assets {
+        let observations = PRICE_OBSERVATIONS.prefix((nft_address.as_bytes(), info.as_bytes()));
+        let last = match observations.range(deps.storage, None, None, CwOrder::Descending).next() {
+            Some(item) => item?.1,
+            None => continue
+        };
+        let cumulative_now = accumulate(&last, now)?;
+        let before_start = observations
+            .range(deps.storage, None, Some(Bound::inclusive(U64Key::new(start).joined_key())), CwOrder::Descending)
+            .next()
+            .transpose()?;
+        let (from, cumulative_from) = match before_start {
+            Some((_, observation)) => (start, accumulate(&observation, start)?),
+            // history is shorter than the window, average over what there is
+            None => match observations.range(deps.storage, None, None, CwOrder::Ascending).next() {
+                Some(item) => {
+                    let (_, first) = item?;
+                    (first.time, first.cumulative)
+                }
+                None => continue
+            }
+        };
+        let observed_seconds = now - from;
+        let amount = if observed_seconds == 0 {
+            last.price
+        } else {
+            cumulative_now.checked_sub(cumulative_from)?.multiply_ratio(1u128, observed_seconds)
+        };
+        prices.push(TwapPrice { price: Asset { info, amount }, observed_seconds });
     }
+    Ok(TwapResponse { nft_address: nft_address.to_string(), window_seconds, prices })
+}
+
+/// Cumulative price of an observation carried forward to `time`
+fn accumulate(observation: &PriceObservation, time: u64) -> StdResult<Uint128> {
+    let held = Uint128::from(time - observation.time);
+    Ok(observation.cumulative.checked_add(observation.price.checked_mul(held)?)?)
+}
+
+fn query_sales(
+    deps: Deps,
+    filter: Option<SaleFilter>,
+    start_after: Option<u64>,
+    limit: Option<u32>
+) -> StdResult<SalesResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let end = start_after.map(|id| Bound::exclusive(U64Key::new(id).joined_key()));
+    let sales_map = sales();
+    let range = match filter {
+        None => sales_map.range(deps.storage, None, end, CwOrder::Descending),
+        Some(SaleFilter::Collection { nft_address }) => sales_map.idx.collection
+            .prefix(deps.api.addr_validate(&nft_address)?.as_bytes().to_vec())
+            .range(deps.storage, None, end, CwOrder::Descending),
+        Some(SaleFilter::Token { nft_address, token_id }) => sales_map.idx.token
+            .prefix((deps.api.addr_validate(&nft_address)?.as_bytes().to_vec(), token_id.as_bytes().to_vec()))
+            .range(deps.storage, None, end, CwOrder::Descending),
+        Some(SaleFilter::Buyer { address }) => sales_map.idx.buyer
+            .prefix(deps.api.addr_validate(&address)?.as_bytes().to_vec())
+            .range(deps.storage, None, end, CwOrder::Descending),
+        Some(SaleFilter::Seller { address }) => sales_map.idx.seller
+            .prefix(deps.api.addr_validate(&address)?.as_bytes().to_vec())
+            .range(deps.storage, None, end, CwOrder::Descending),
+    };
+    let sales = range
+        .take(limit)
+        .map(|item| item.map(|(_, sale)| sale))
+        .collect::<StdResult<Vec<_>>>()?;
+    Ok(SalesResponse { sales })
 }
 
 pub fn set_paused(
     deps: DepsMut,
     _env: Env,
-    _info: MessageInfo,
-    paused: bool,
-) -> Result<Response, ContractError> {
+    info: MessageInfo,
+    operations: Vec<Operation>,
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    assert_owner(deps.storage, &info.sender)?;
     let output = CONFIG.update(deps.storage, |mut c| -> StdResult<_> {
-        c.paused = paused;
+        c.paused_operations = Operation::to_bits(&operations);
         Ok(c)
     })?;
     Ok(Response::new()
         .add_attribute("action", "set_paused")
-        .add_attribute("paused", output.paused.to_string())
+        .add_attribute("paused", join_operations(output.paused_operations))
     )
 }
 
-pub fn create_order(
+pub fn set_collection_paused(
     deps: DepsMut,
-    env: Env,
+    _env: Env,
     info: MessageInfo,
-    token_id: String,
     nft_address: String,
-    price: Asset,
-    expire_at: u64
-) -> Result<Response, ContractError> {
-    let con = CONFIG.load(deps.storage)?;
-    if con.paused {
-        return Err(ContractError:: MarketplacePaused{});
-    }
-    let owner_res: OwnerOfResponse =
-    deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
-        contract_addr: nft_address.clone(),
-        msg: to_binary(&Cw721QueryMsg::OwnerOf { token_id: token_id.clone(), include_expired: Some(false) })?,
-    })).unwrap();
-    if owner_res.owner != info.sender.to_string() {
-        return Err(ContractError::NoOwner {})
+    operations: Vec<Operation>,
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    assert_owner(deps.storage, &info.sender)?;
+    let nft_address = deps.api.addr_validate(&nft_address)?;
+    let bits = Operation::to_bits(&operations);
+    if bits == 0 {
+        COLLECTION_PAUSES.remove(deps.storage, nft_address.as_str());
+    } else {
+        COLLECTION_PAUSES.save(deps.storage, nft_address.as_str(), &bits)?;
     }
-    let res = _create_order(deps, env, info, token_id, nft_address, price, expire_at).unwrap();
-    Ok(res)
+    Ok(Response::new()
+        .add_attribute("action", "set_collection_paused")
+        .add_attribute("nft_address", nft_address)
+        .add_attribute("paused", join_operations(bits))
+    )
 }
 
-pub fn update_order(
+pub fn set_emergency(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    emergency: bool,
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    assert_owner(deps.storage, &info.sender)?;
+    CONFIG.update(deps.storage, |mut c| -> StdResult<_> {
+        c.emergency = emergency;
+        Ok(c)
+    })?;
+    Ok(Response::new()
+        .add_attribute("action", "set_emergency")
+        .add_attribute("emergency", emergency.to_string())
+    )
+}
+
+pub fn set_withdrawal_mode(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
-    token_id: String,
-    nft_address: String,
-    price: Asset,
-    expire_at: u64
-) -> Result<Response, ContractError> {
-    let con = CONFIG.load(deps.storage)?;
-    if con.paused {
-        return Err(ContractError:: MarketplacePaused{});
+    enabled: bool,
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    assert_owner(deps.storage, &info.sender)?;
+    CONFIG.update(deps.storage, |mut c| -> StdResult<_> {
+        c.withdrawal_mode = enabled;
+        Ok(c)
+    })?;
+    let report = query_escrow_balances(deps.as_ref(), env)?;
+    Ok(Response::new()
+        .add_attribute("action", "set_withdrawal_mode")
+        .add_attribute("enabled", enabled.to_string())
+        .add_attribute("solvent", report.solvent.to_string())
+    )
+}
+
+fn join_operations(bits: u8) -> String {
+    Operation::from_bits(bits).iter()
+        .map(|op| format!("{:?}", op).to_lowercase())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/**
+ * @dev Fail if `operation` is paused globally or for any of `nft_addresses`.
+ *  Cancelling is only ever paused while the emergency flag is set.
+ */
+fn assert_not_paused(storage: &dyn Storage, operation: Operation, nft_addresses: &[&str]) -> Result<(), ContractError> {
+    let con = CONFIG.load(storage)?;
+    if operation == Operation::Cancelling && !con.emergency {
+        return Ok(());
+    }
+    if con.withdrawal_mode && operation != Operation::Cancelling {
+        return Err(ContractError::MarketplacePaused {});
+    }
+    let mut bits = con.paused_operations;
+    for nft_address in nft_addresses {
+        bits |= COLLECTION_PAUSES.may_load(storage, nft_address)?.unwrap_or_default();
+    }
+    if bits & operation.bit() != 0 {
+        return Err(ContractError::MarketplacePaused {});
     }
-    let res = _update_order(deps, env, info, token_id, nft_address, price, expire_at).unwrap();
-    Ok(res)
+    Ok(())
 }
 
-pub fn cancel_order(
+const MAX_KEEPER_FEE: u64 = 1;
+
+pub fn set_keeper_fee(
     deps: DepsMut,
-    env: Env,
+    _env: Env,
     info: MessageInfo,
-    token_id: String,
-    nft_address: String
-) -> Result<Response, ContractError> {
-    let con = CONFIG.load(deps.storage)?;
-    if con.paused {
-        return Err(ContractError:: MarketplacePaused{});
+    keeper_fee: Decimal,
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    assert_owner(deps.storage, &info.sender)?;
+    let max = Decimal::percent(MAX_KEEPER_FEE);
+    if keeper_fee > max {
+        return Err(ContractError::InvalidKeeperFee { max });
     }
-    let res = _cancel_order(deps, env, info, token_id, nft_address).unwrap();
-    Ok(res)
+    CONFIG.update(deps.storage, |mut c| -> StdResult<_> {
+        c.keeper_fee = keeper_fee;
+        Ok(c)
+    })?;
+    Ok(Response::new()
+        .add_attribute("action", "set_keeper_fee")
+        .add_attribute("keeper_fee", keeper_fee.to_string())
+    )
 }
 
-pub fn safe_execute_order(
+/**
+ * @dev Pay out the fees accrued from sales, tax deducted, to `recipient` or the owner.
+ *  Assets that accrued nothing are skipped.
+ */
+pub fn claim_fees(
     deps: DepsMut,
-    env: Env,
+    _env: Env,
     info: MessageInfo,
-    token_id: String,
-    nft_address: String,
-    price: Asset
-) -> Result<Response, ContractError> {
-    let con = CONFIG.load(deps.storage)?;
-    if con.paused {
-        return Err(ContractError:: MarketplacePaused{});
+    recipient: Option<String>,
+    assets: Option<Vec<AssetInfo>>
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    let con = assert_owner(deps.storage, &info.sender)?;
+    let recipient = match recipient {
+        Some(recipient) => deps.api.addr_validate(&recipient)?,
+        None => con.owner
+    };
+    let fees = ACCRUED_FEES.range(deps.storage, None, None, CwOrder::Ascending)
+        .map(|item| item.map(|(_, fee)| fee))
+        .collect::<StdResult<Vec<Asset>>>()?;
+    let mut messages: Vec<CosmosMsg<TerraMsgWrapper>> = vec![];
+    for fee in fees {
+        if fee.amount.is_zero() || matches!(&assets, Some(assets) if !assets.contains(&fee.info)) {
+            continue;
+        }
+        ACCRUED_FEES.remove(deps.storage, fee.info.as_bytes());
+        messages.push(fee.into_msg(&deps.querier, recipient.clone())?);
     }
-    let res = _safe_execute_order(deps, env, info, token_id, nft_address, price).unwrap();
-    Ok(res)
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attribute("action", "claim_fees")
+        .add_attribute("recipient", recipient)
+    )
 }
 
-pub fn create_bid(
+pub fn set_listing_durations(
     deps: DepsMut,
-    env: Env,
+    _env: Env,
     info: MessageInfo,
-    token_id: String,
-    nft_address: String,
-    price: Asset,
-    expire_at: u64
-) -> Result<Response, ContractError> {
-    let con = CONFIG.load(deps.storage)?;
-    if con.paused {
-        return Err(ContractError:: MarketplacePaused{});
+    durations: ListingDurations,
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    assert_owner(deps.storage, &info.sender)?;
+    if matches!(durations.max_time, Some(max) if max < durations.min_time)
+        || matches!(durations.max_height, Some(max) if max < durations.min_height) {
+        return Err(ContractError::InvalidExpiration {});
     }
-    let res = _create_bid(deps, env, info, token_id, nft_address, price, expire_at).unwrap();
-    Ok(res)
+    CONFIG.update(deps.storage, |mut c| -> StdResult<_> {
+        c.listing_durations = durations;
+        Ok(c)
+    })?;
+    Ok(Response::new()
+        .add_attribute("action", "set_listing_durations")
+    )
 }
 
-pub fn accept_bid(
+pub fn set_curators(
     deps: DepsMut,
-    env: Env,
+    _env: Env,
+    info: MessageInfo,
+    curators: Vec<String>,
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    assert_owner(deps.storage, &info.sender)?;
+    let curators = curators.iter()
+        .map(|c| deps.api.addr_validate(c))
+        .collect::<StdResult<Vec<_>>>()?;
+    CONFIG.update(deps.storage, |mut c| -> StdResult<_> {
+        c.curators = curators.clone();
+        Ok(c)
+    })?;
+    Ok(Response::new()
+        .add_attribute("action", "set_curators")
+        .add_attribute("curators", curators.iter().map(|c| c.as_str()).collect::<Vec<_>>().join(","))
+    )
+}
+
+pub fn set_require_verified(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    require_verified: bool,
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    assert_owner(deps.storage, &info.sender)?;
+    CONFIG.update(deps.storage, |mut c| -> StdResult<_> {
+        c.require_verified = require_verified;
+        Ok(c)
+    })?;
+    Ok(Response::new()
+        .add_attribute("action", "set_require_verified")
+        .add_attribute("require_verified", require_verified.to_string())
+    )
+}
+
+const MAX_ROYALTY_RATE: u64 = 10;
+
+#[allow(clippy::too_many_arguments)]
+pub fn set_collection(
+    deps: DepsMut,
+    _env: Env,
     info: MessageInfo,
-    token_id: String,
     nft_address: String,
-    price: Asset
-) -> Result<Response, ContractError> {
-    let con = CONFIG.load(deps.storage)?;
-    if con.paused {
-        return Err(ContractError:: MarketplacePaused{});
+    name: String,
+    creator: String,
+    royalty_rate: Decimal,
+    status: CollectionStatus
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    assert_curator(deps.storage, &info.sender)?;
+    let max = Decimal::percent(MAX_ROYALTY_RATE);
+    if royalty_rate > max {
+        return Err(ContractError::InvalidRoyalty { max });
     }
-    let res = _accept_bid(deps, env, info, token_id, nft_address, price).unwrap();
-    Ok(res)
+    let nft_address = deps.api.addr_validate(&nft_address)?;
+    let collection = CollectionInfo {
+        name,
+        creator: deps.api.addr_validate(&creator)?,
+        royalty_rate,
+        status
+    };
+    COLLECTIONS.save(deps.storage, nft_address.as_str(), &collection)?;
+    Ok(Response::new()
+        .add_attribute("action", "set_collection")
+        .add_attribute("nft_address", nft_address)
+        .add_attribute("status", format!("{:?}", collection.status).to_lowercase())
+    )
 }
 
-pub fn cancel_bid(
+pub fn remove_collection(
     deps: DepsMut,
     _env: Env,
-    _info: MessageInfo,
-    token_id: String,
+    info: MessageInfo,
     nft_address: String
-) -> Result<Response, ContractError> {
-    let con = CONFIG.load(deps.storage)?;
-    if con.paused {
-        return Err(ContractError:: MarketplacePaused{});
-    }
-    let mut messages: Vec<CosmosMsg> = vec![];
-    messages.push(_cancel_bid(deps.storage, &deps.querier, token_id.clone(), nft_address.clone()).unwrap());
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    assert_curator(deps.storage, &info.sender)?;
+    COLLECTIONS.remove(deps.storage, &nft_address);
     Ok(Response::new()
-        .add_messages(messages)
-        .add_attribute("action", "cancel_bid")
-        .add_attribute("token_id", token_id)
+        .add_attribute("action", "remove_collection")
         .add_attribute("nft_address", nft_address)
     )
 }
 
-fn _create_order(
+pub fn set_moderators(
     deps: DepsMut,
-    env: Env,
+    _env: Env,
     info: MessageInfo,
-    token_id: String,
-    nft_address: String,
-    price: Asset,
-    expire_at: u64
-) -> Result<Response, ContractError> {
-    if price.amount <= Uint128::zero() {
-        return Err(ContractError::InvalidPrice {})
-    }
-    if expire_at < env.block.time.seconds() + 60u64 {
-        return Err(ContractError::InvalidExpiration {});
-    }
-    let time_str = env.block.time.seconds().to_string();
-    //get NFT asset to seller - should be called from frontend
-    let order = Order {
-        token_id: token_id.clone(),
-        nft_address: deps.api.addr_validate(&nft_address)?,
-        seller: deps.api.addr_validate(info.sender.as_str())?,
-        price: price,
-        expire_at: expire_at.clone()
-    };
-    ORDERS.save(deps.storage, (&token_id, &nft_address), &order)?;
+    moderators: Vec<String>,
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    assert_owner(deps.storage, &info.sender)?;
+    let moderators = moderators.iter()
+        .map(|m| deps.api.addr_validate(m))
+        .collect::<StdResult<Vec<_>>>()?;
+    CONFIG.update(deps.storage, |mut c| -> StdResult<_> {
+        c.moderators = moderators.clone();
+        Ok(c)
+    })?;
     Ok(Response::new()
-        .add_attribute("action", "create_order")
-        .add_attribute("token_id", order.token_id)
-        .add_attribute("nft_address", order.nft_address)
-        .add_attribute("seller", order.seller)
-        .add_attribute("expire_at", time_str)
-        .add_attribute("price", order.price.amount)
+        .add_attribute("action", "set_moderators")
+        .add_attribute("moderators", moderators.iter().map(|m| m.as_str()).collect::<Vec<_>>().join(","))
     )
 }
 
-fn _create_bid(
+/**
+ * @dev Take down an order, e.g. of a stolen NFT. Any bid on it is refunded,
+ *  the NFT goes to the seller or to `recovery_address` and the reason is kept.
+ */
+pub fn force_cancel_order(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
     token_id: String,
     nft_address: String,
-    price: Asset,
-    expire_at: u64
-) -> Result<Response, ContractError> {
-    let order = ORDERS.load(deps.storage, (&token_id, &nft_address))?;
-    if expire_at < env.block.time.seconds() {
-        return Err(ContractError::Expired {});
-    }
-
-    if order.price.amount > price.amount {
-        return Err(ContractError::MinPrice { min_bid_amount: price.amount })
-    }
-    let mut messages: Vec<CosmosMsg> = vec![];
+    recovery_address: Option<String>,
+    reason: String
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    assert_moderator(deps.storage, &info.sender)?;
+    let order = orders().may_load(deps.storage, (&token_id, &nft_address))?
+        .ok_or(ContractError::NoOrder {})?;
+    let recipient = match recovery_address {
+        Some(address) => deps.api.addr_validate(&address)?,
+        None => order.seller.clone()
+    };
 
-    let has_bid = BIDS.has(deps.storage, (&token_id, &nft_address));
-    if has_bid {
-        let bid = BIDS.load(deps.storage, (&token_id, &nft_address))?;
-        if bid.expire_at < env.block.time.seconds() {
-            if price.amount <= Uint128::zero() {
-                return Err(ContractError::ZeroBidAmount {});
-            }            
-        } else {
-            if price.amount < bid.price.amount {
-                return Err(ContractError::InvalidBidAmount {});
-            }
-        }
-        messages.push(_cancel_bid(deps.storage, &deps.querier, token_id.clone(), nft_address.clone()).unwrap())
-    } else {
-        if price.amount <= Uint128::zero() {
-            return Err(ContractError::ZeroBidAmount {});
-        }
+    let mut messages: Vec<CosmosMsg<TerraMsgWrapper>> = vec![];
+    if bids().may_load(deps.storage, (&token_id, &nft_address))?.is_some() {
+        messages.extend(_cancel_bid(deps.storage, &deps.querier, token_id.clone(), nft_address.clone())?)
     }
-    //Transfer sale amount from bidder escrow- should be done from coin params on execution
-    let bid = Bid {
-        token_id: token_id.clone(),
-        nft_address: deps.api.addr_validate(&nft_address)?,
-        bidder: deps.api.addr_validate(info.sender.as_str())?,
+    messages.extend(nft_transfer_messages(
+        &[BundleItem { token_id: order.token_id.clone(), nft_address: order.nft_address.clone() }],
+        &recipient
+    )?);
+    remove_order(deps.storage, &token_id, &nft_address)?;
+    DELISTINGS.save(deps.storage, (&token_id, &nft_address), &Delisting {
         seller: order.seller,
-        price: price,
-        expire_at: expire_at
-    };
-    BIDS.save(deps.storage, (&token_id, &nft_address), &bid)?;
+        recipient: recipient.clone(),
+        moderator: info.sender,
+        reason: reason.clone(),
+        at: env.block.time.seconds()
+    })?;
     Ok(Response::new()
         .add_messages(messages)
-        .add_attribute("action", "create_bid")
+        .add_attribute("action", "force_cancel_order")
         .add_attribute("token_id", token_id)
         .add_attribute("nft_address", nft_address)
-        .add_attribute("bidder", info.sender.to_string())
+        .add_attribute("recipient", recipient)
+        .add_attribute("reason", reason)
     )
 }
 
-/**
- * @dev Cancel an already published order
- *  can only be canceled by seller or the contract owner
- */
-fn _cancel_order(
+pub fn set_token_blocked(
     deps: DepsMut,
     _env: Env,
     info: MessageInfo,
     token_id: String,
-    nft_address: String
-) -> Result<Response, ContractError> {
-
-    let order = ORDERS.load(deps.storage, (&token_id, &nft_address))?;
-    // only seller cancel order
-    if order.seller != info.sender {
-        return Err(ContractError::Unauthorized {});
+    nft_address: String,
+    blocked: bool
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    assert_moderator(deps.storage, &info.sender)?;
+    if blocked {
+        BLOCKED_TOKENS.save(deps.storage, (&token_id, &nft_address), &true)?;
+    } else {
+        BLOCKED_TOKENS.remove(deps.storage, (&token_id, &nft_address));
     }
+    Ok(Response::new()
+        .add_attribute("action", "set_token_blocked")
+        .add_attribute("token_id", token_id)
+        .add_attribute("nft_address", nft_address)
+        .add_attribute("blocked", blocked.to_string())
+    )
+}
 
-    let mut messages: Vec<CosmosMsg> = vec![];
-    let has_bid = BIDS.has(deps.storage, (&token_id, &nft_address));
-    if has_bid {
-        messages.push(_cancel_bid(deps.storage, &deps.querier, token_id.clone(), nft_address.clone()).unwrap())
+pub fn set_address_blocked(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    address: String,
+    blocked: bool
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    assert_moderator(deps.storage, &info.sender)?;
+    let address = deps.api.addr_validate(&address)?;
+    if blocked {
+        BLOCKED_ADDRESSES.save(deps.storage, address.as_str(), &true)?;
+    } else {
+        BLOCKED_ADDRESSES.remove(deps.storage, address.as_str());
     }
+    Ok(Response::new()
+        .add_attribute("action", "set_address_blocked")
+        .add_attribute("address", address)
+        .add_attribute("blocked", blocked.to_string())
+    )
+}
 
-    //  send asset back to seller
-    messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
-        contract_addr: order.nft_address.to_string(),
-        msg: to_binary(&Cw721ExecuteMsg::TransferNft {
-          recipient: order.seller.to_string(), 
-          token_id: order.token_id
-        })?,
-        funds: vec![]
-      })
-    );
-    //remove order
-    ORDERS.remove(deps.storage, (&token_id, &nft_address));
+pub fn update_accepted_assets(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    assets: Vec<AssetInfo>,
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    assert_owner(deps.storage, &info.sender)?;
+    let assets = validate_asset_infos(assets)?;
+    ACCEPTED_ASSETS.save(deps.storage, &assets)?;
     Ok(Response::new()
-        .add_messages(messages)
-        .add_attribute("action", "cancel_order")
-        .add_attribute("token_id", token_id)
+        .add_attribute("action", "update_accepted_assets")
+        .add_attribute("assets", join_asset_infos(&assets))
+    )
+}
+
+/**
+ * @dev Override the accepted payment assets of a single collection,
+ *  `None` falls back to the global list again
+ */
+pub fn set_collection_accepted_assets(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    nft_address: String,
+    assets: Option<Vec<AssetInfo>>,
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    assert_owner(deps.storage, &info.sender)?;
+    let nft_address = deps.api.addr_validate(&nft_address)?;
+    let assets_attr = match assets {
+        Some(assets) => {
+            let assets = validate_asset_infos(assets)?;
+            COLLECTION_ACCEPTED_ASSETS.save(deps.storage, nft_address.as_str(), &assets)?;
+            join_asset_infos(&assets)
+        }
+        None => {
+            COLLECTION_ACCEPTED_ASSETS.remove(deps.storage, nft_address.as_str());
+            "default".to_string()
+        }
+    };
+    Ok(Response::new()
+        .add_attribute("action", "set_collection_accepted_assets")
         .add_attribute("nft_address", nft_address)
+        .add_attribute("assets", assets_attr)
     )
 }
 
-fn _update_order(
+pub fn set_collection_fee(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    nft_address: String,
+    rate: Option<Decimal>
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    let con = assert_owner(deps.storage, &info.sender)?;
+    let nft_address = deps.api.addr_validate(&nft_address)?;
+    let rate_attr = match rate {
+        Some(rate) => {
+            if rate > con.owner_cut_rate_max {
+                return Err(ContractError::InvalidFeeRate { max: con.owner_cut_rate_max });
+            }
+            COLLECTION_FEES.save(deps.storage, nft_address.as_str(), &rate)?;
+            rate.to_string()
+        }
+        None => {
+            COLLECTION_FEES.remove(deps.storage, nft_address.as_str());
+            "default".to_string()
+        }
+    };
+    Ok(Response::new()
+        .add_attribute("action", "set_collection_fee")
+        .add_attribute("nft_address", nft_address)
+        .add_attribute("rate", rate_attr)
+    )
+}
+
+pub fn set_fee_tiers(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    tiers: Vec<FeeTier>
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    let con = assert_owner(deps.storage, &info.sender)?;
+    if tiers.iter().any(|tier| tier.rate > con.owner_cut_rate_max) {
+        return Err(ContractError::InvalidFeeRate { max: con.owner_cut_rate_max });
+    }
+    validate_asset_infos(tiers.iter().map(|tier| tier.asset_info.clone()).collect())?;
+    let count = tiers.len();
+    CONFIG.update(deps.storage, |mut c| -> StdResult<_> {
+        c.fee_tiers = tiers;
+        Ok(c)
+    })?;
+    Ok(Response::new()
+        .add_attribute("action", "set_fee_tiers")
+        .add_attribute("tiers", count.to_string())
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn create_order(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
     token_id: String,
     nft_address: String,
     price: Asset,
-    expire_at: u64
-    
-) -> Result<Response, ContractError> {
-
-    if !ORDERS.has(deps.storage, (&token_id, &nft_address)) {
-        return Err(ContractError::NoOrder {});
-    }
-    let mut order = ORDERS.load(deps.storage, (&token_id, &nft_address))?;
-    if order.expire_at < env.block.time.seconds() {
-        return Err(ContractError::Expired {});
-    } 
-    if price.amount <= Uint128::zero() {
-        return Err(ContractError::InvalidPrice {});
-    }
-    if expire_at < env.block.time.seconds() + 60u64 {
-        return Err(ContractError::InvalidExpiration {});
+    expire_at: Expiration,
+    payout_swap: Option<PayoutSwap>,
+    reserved_for: Option<String>
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    assert_not_paused(deps.storage, Operation::Listing, &[&nft_address])?;
+    assert_accepted_asset(deps.storage, &nft_address, &price.info)?;
+    let owner = query_nft_owner(&deps.querier, &nft_address, &token_id)?;
+    if owner != info.sender {
+        return Err(ContractError::NoOwner {})
     }
-    // only seller update order
-    if order.seller != info.sender {
+    _create_order(deps, env, info, token_id, nft_address, price, expire_at, payout_swap, reserved_for)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn update_order(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    token_id: String,
+    nft_address: String,
+    price: Asset,
+    expire_at: Expiration,
+    payout_swap: Option<PayoutSwap>,
+    reserved_for: Option<String>
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    assert_not_paused(deps.storage, Operation::Listing, &[&nft_address])?;
+    assert_accepted_asset(deps.storage, &nft_address, &price.info)?;
+    _update_order(deps, env, info, token_id, nft_address, price, expire_at, payout_swap, reserved_for)
+}
+
+pub fn cancel_order(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    token_id: String,
+    nft_address: String
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    assert_not_paused(deps.storage, Operation::Cancelling, &[])?;
+    _cancel_order(deps, env, info, token_id, nft_address)
+}
+
+pub fn safe_execute_order(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    token_id: String,
+    nft_address: String,
+    price: Asset
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    assert_not_paused(deps.storage, Operation::Buying, &[&nft_address])?;
+    _safe_execute_order(deps, env, info, token_id, nft_address, price)
+}
+
+pub fn create_bid(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    token_id: String,
+    nft_address: String,
+    price: Asset,
+    expire_at: Expiration
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    assert_not_paused(deps.storage, Operation::Bidding, &[&nft_address])?;
+    assert_accepted_asset(deps.storage, &nft_address, &price.info)?;
+    _create_bid(deps, env, info, token_id, nft_address, price, expire_at)
+}
+
+pub fn accept_bid(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    token_id: String,
+    nft_address: String,
+    price: Asset
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    assert_not_paused(deps.storage, Operation::Accepting, &[&nft_address])?;
+    _accept_bid(deps, env, info, token_id, nft_address, price)
+}
+
+pub fn cancel_bid(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    token_id: String,
+    nft_address: String
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    assert_not_paused(deps.storage, Operation::Cancelling, &[])?;
+    let bid = bids().may_load(deps.storage, (&token_id, &nft_address))?
+        .ok_or(ContractError::NoBid {})?;
+    // only bidder cancel bid
+    if bid.bidder != info.sender {
         return Err(ContractError::Unauthorized {});
     }
-    order.price = price.clone();
-    order.expire_at = expire_at.clone();
-    ORDERS.save(deps.storage, (&token_id, &nft_address), &order)?;   
+    let refund = _cancel_bid(deps.storage, &deps.querier, token_id.clone(), nft_address.clone())?;
     Ok(Response::new()
-        .add_attribute("action", "update_order")
+        .add_messages(refund)
+        .add_attribute("action", "cancel_bid")
         .add_attribute("token_id", token_id)
         .add_attribute("nft_address", nft_address)
     )
 }
 
-fn _cancel_bid(
-    storage: &mut dyn Storage,
-    querier: &QuerierWrapper,
+#[allow(clippy::too_many_arguments)]
+fn _create_order(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
     token_id: String,
-    nft_address: String
-) -> StdResult<CosmosMsg> {
-    let bid = BIDS.load(storage, (&token_id, &nft_address))?;
-    let message = bid.price.into_msg(querier, bid.bidder)?;
-    BIDS.remove(storage, (&token_id, &nft_address));
-    Ok(message)
+    nft_address: String,
+    price: Asset,
+    expire_at: Expiration,
+    payout_swap: Option<PayoutSwap>,
+    reserved_for: Option<String>
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    if price.amount <= Uint128::zero() {
+        return Err(ContractError::InvalidPrice {})
+    }
+    let con = CONFIG.load(deps.storage)?;
+    assert_listable(deps.storage, &con, &nft_address)?;
+    assert_token_not_blocked(deps.storage, &token_id, &nft_address)?;
+    assert_not_blocked(deps.storage, &[&info.sender])?;
+    assert_valid_expiration(&con, &env.block, &expire_at)?;
+    assert_valid_payout_swap(deps.storage, &info.sender, &price, &payout_swap)?;
+    //get NFT asset to seller - should be called from frontend
+    let order = Order {
+        token_id: token_id.clone(),
+        nft_address: deps.api.addr_validate(&nft_address)?,
+        seller: deps.api.addr_validate(info.sender.as_str())?,
+        price,
+        expire_at,
+        payout_swap,
+        reserved_for: reserved_for.map(|a| deps.api.addr_validate(&a)).transpose()?
+    };
+    save_order(deps.storage, &order)?;
+    Ok(Response::new()
+        .add_attribute("action", "create_order")
+        .add_attribute("token_id", order.token_id)
+        .add_attribute("nft_address", order.nft_address)
+        .add_attribute("seller", order.seller)
+        .add_attribute("expire_at", order.expire_at.to_string())
+        .add_attribute("price", order.price.amount)
+    )
 }
 
-fn _safe_execute_order(
+fn _create_bid(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     info: MessageInfo,
     token_id: String,
     nft_address: String,
-    price: Asset
-) -> Result<Response, ContractError> {
-
-    if !ORDERS.has(deps.storage, (&token_id, &nft_address)) {
-        return Err(ContractError::NoOrder {});
+    price: Asset,
+    expire_at: Expiration
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    let order = orders().may_load(deps.storage, (&token_id, &nft_address))?
+        .ok_or(ContractError::NoOrder {})?;
+    assert_not_reserved(&order, &info.sender)?;
+    assert_token_not_blocked(deps.storage, &token_id, &nft_address)?;
+    assert_not_blocked(deps.storage, &[&info.sender])?;
+    if expire_at.is_expired(&env.block) {
+        return Err(ContractError::Expired {});
     }
-    let order = ORDERS.load(deps.storage, (&token_id, &nft_address))?;
-    if order.price.info != price.info || order.price.amount != price.amount {
+
+    // bids under the asking price are fine, the seller may accept or counter them
+    if order.price.info != price.info {
         return Err(ContractError::InvalidPrice {});
     }
-    if order.seller == info.sender {
-        return Err(ContractError::Unauthorized {});
-    }
-    // Transfer all amount by coin param on calling
-    // it should be performed from frontend by coin params.
-    let mut messages: Vec<CosmosMsg> = vec![];
-    let con = CONFIG.load(deps.storage)?;
-    if con.owner_cut_rate > Decimal::zero() {
-        let sales_share_amount_asset = Asset {
-            info: order.price.info.clone(),
-            amount: order.price.amount * con.owner_cut_rate
-        };
-        messages.push(sales_share_amount_asset.into_msg(&deps.querier, con.owner.clone())?);
+    // the whole bid is escrowed with the message
+    price.assert_sent_native_token_balance(&info)?;
+    let mut messages: Vec<CosmosMsg<TerraMsgWrapper>> = vec![];
+
+    if let Some(bid) = bids().may_load(deps.storage, (&token_id, &nft_address))? {
+        if bid.expire_at.is_expired(&env.block) {
+            if price.amount <= Uint128::zero() {
+                return Err(ContractError::ZeroBidAmount {});
+            }            
+        } else {
+            if price.amount < bid.price.amount {
+                return Err(ContractError::InvalidBidAmount {});
+            }
+        }
+        messages.extend(_cancel_bid(deps.storage, &deps.querier, token_id.clone(), nft_address.clone())?)
+    } else if price.amount <= Uint128::zero() {
+        return Err(ContractError::ZeroBidAmount {});
     }
-    let seller_amount_asset = Asset {
-        info: order.price.info.clone(),
-        amount: order.price.amount - (order.price.amount * con.owner_cut_rate)
+    //Transfer sale amount from bidder escrow- should be done from coin params on execution
+    let bid = Bid {
+        token_id: token_id.clone(),
+        nft_address: deps.api.addr_validate(&nft_address)?,
+        bidder: deps.api.addr_validate(info.sender.as_str())?,
+        seller: order.seller,
+        price,
+        expire_at
     };
-    messages.push(seller_amount_asset.into_msg(&deps.querier, order.seller.clone())?);
+    save_bid(deps.storage, &bid)?;
+    record_negotiation(deps.storage, &token_id, &nft_address, NegotiationAction::Bid, &bid.bidder, &bid.price, env.block.time.seconds())?;
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attribute("action", "create_bid")
+        .add_attribute("token_id", token_id)
+        .add_attribute("nft_address", nft_address)
+        .add_attribute("bidder", info.sender.to_string())
+    )
+}
 
-    // remove bids and orders
-    if BIDS.has(deps.storage, (&token_id, &nft_address)) {
-        messages.push(_cancel_bid(deps.storage, &deps.querier, token_id.clone(), nft_address.clone()).unwrap());
+/**
+ * @dev Cancel an already published order
+ *  can only be canceled by seller or the contract owner
+ */
+fn _cancel_order(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    token_id: String,
+    nft_address: String
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+
+    let order = orders().may_load(deps.storage, (&token_id, &nft_address))?
+        .ok_or(ContractError::NoOrder {})?;
+    // only seller cancel order
+    if order.seller != info.sender {
+        return Err(ContractError::Unauthorized {});
     }
+
+    let mut messages: Vec<CosmosMsg<TerraMsgWrapper>> = vec![];
+    if bids().may_load(deps.storage, (&token_id, &nft_address))?.is_some() {
+        messages.extend(_cancel_bid(deps.storage, &deps.querier, token_id.clone(), nft_address.clone())?)
+    }
+
+    //  send asset back to seller
     messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
         contract_addr: order.nft_address.to_string(),
         msg: to_binary(&Cw721ExecuteMsg::TransferNft {
-          recipient: info.sender.to_string(), 
-          token_id: order.token_id.clone()
+          recipient: order.seller.to_string(), 
+          token_id: order.token_id
         })?,
         funds: vec![]
       })
     );
-    ORDERS.remove(deps.storage, (&token_id, &nft_address));
+    //remove order
+    remove_order(deps.storage, &token_id, &nft_address)?;
     Ok(Response::new()
         .add_messages(messages)
-        .add_attribute("action", "_safe_execute_order")
+        .add_attribute("action", "cancel_order")
         .add_attribute("token_id", token_id)
         .add_attribute("nft_address", nft_address)
     )
 }
 
-fn _accept_bid(
+#[allow(clippy::too_many_arguments)]
+fn _update_order(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
     token_id: String,
     nft_address: String,
-    price: Asset
-) -> Result<Response, ContractError> {
-
-    let con = CONFIG.load(deps.storage)?;
+    price: Asset,
+    expire_at: Expiration,
+    payout_swap: Option<PayoutSwap>,
+    reserved_for: Option<String>
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
 
-    //Frontend -  send coin amount as param
-    if !ORDERS.has(deps.storage, (&token_id, &nft_address)) {
-        return Err(ContractError::NoOrder {});
+    let mut order = orders().may_load(deps.storage, (&token_id, &nft_address))?
+        .ok_or(ContractError::NoOrder {})?;
+    if order.expire_at.is_expired(&env.block) {
+        return Err(ContractError::Expired {});
     }
-    let order = ORDERS.load(deps.storage, (&token_id, &nft_address))?;
-    // only seller approve order
+    if price.amount <= Uint128::zero() {
+        return Err(ContractError::InvalidPrice {});
+    }
+    let con = CONFIG.load(deps.storage)?;
+    assert_valid_expiration(&con, &env.block, &expire_at)?;
+    // only seller update order
     if order.seller != info.sender {
         return Err(ContractError::Unauthorized {});
     }
-    if order.expire_at < env.block.time.seconds() {
-        return Err(ContractError::Expired {})
-    }
-
-    if !BIDS.has(deps.storage, (&token_id, &nft_address)) {
-        return Err(ContractError::NoBid {});
-    }
-    let bid = BIDS.load(deps.storage, (&token_id, &nft_address))?;
+    assert_valid_payout_swap(deps.storage, &info.sender, &price, &payout_swap)?;
+    order.price = price;
+    order.expire_at = expire_at;
+    order.payout_swap = payout_swap;
+    order.reserved_for = reserved_for.map(|a| deps.api.addr_validate(&a)).transpose()?;
+    orders().save(deps.storage, (&token_id, &nft_address), &order)?;   
+    Ok(Response::new()
+        .add_attribute("action", "update_order")
+        .add_attribute("token_id", token_id)
+        .add_attribute("nft_address", nft_address)
+    )
+}
 
-    // price validation - native sent balance check
-    price.assert_sent_native_token_balance(&info)?;
+#[allow(clippy::too_many_arguments)]
+pub fn counter_offer(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    token_id: String,
+    nft_address: String,
+    bidder: String,
+    price: Asset,
+    expire_at: Expiration
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    assert_not_paused(deps.storage, Operation::Bidding, &[&nft_address])?;
+    _counter_offer(deps, env, info, token_id, nft_address, bidder, price, expire_at)
+}
 
-    if bid.price.info != price.info || bid.price.amount != price.amount {
-        return Err(ContractError::InvalidPrice {});
+pub fn accept_counter_offer(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    token_id: String,
+    nft_address: String,
+    price: Asset
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    assert_not_paused(deps.storage, Operation::Accepting, &[&nft_address])?;
+    _accept_counter_offer(deps, env, info, token_id, nft_address, price)
+}
+
+/**
+ * @dev Answer the standing bid with a higher price that only its bidder can accept.
+ *  A new counter-offer replaces the previous one, it lapses with the bid.
+ */
+#[allow(clippy::too_many_arguments)]
+fn _counter_offer(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    token_id: String,
+    nft_address: String,
+    bidder: String,
+    price: Asset,
+    expire_at: Expiration
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    let now = env.block.time.seconds();
+    let order = orders().may_load(deps.storage, (&token_id, &nft_address))?
+        .ok_or(ContractError::NoOrder {})?;
+    // only seller counters a bid
+    if order.seller != info.sender {
+        return Err(ContractError::Unauthorized {});
     }
-    if bid.expire_at < env.block.time.seconds() {
-        return Err(ContractError::BidExpired {})
+    if order.expire_at.is_expired(&env.block) {
+        return Err(ContractError::Expired {});
+    }
+    let bid = bids().may_load(deps.storage, (&token_id, &nft_address))?
+        .ok_or(ContractError::NoBid {})?;
+    if bid.bidder != deps.api.addr_validate(&bidder)? {
+        return Err(ContractError::NoBid {});
+    }
+    if bid.expire_at.is_expired(&env.block) {
+        return Err(ContractError::BidExpired {});
     }
+    // meet somewhere between the bid and the asking price
+    if bid.price.info != price.info || price.amount <= bid.price.amount || price.amount > order.price.amount {
+        return Err(ContractError::InvalidPrice {});
+    }
+    let con = CONFIG.load(deps.storage)?;
+    assert_valid_expiration(&con, &env.block, &expire_at)?;
+
+    let counter_offer = CounterOffer {
+        bidder: bid.bidder,
+        price,
+        expire_at
+    };
+    COUNTER_OFFERS.save(deps.storage, (&token_id, &nft_address), &counter_offer)?;
+    record_negotiation(deps.storage, &token_id, &nft_address, NegotiationAction::CounterOffer, &info.sender, &counter_offer.price, now)?;
+    Ok(Response::new()
+        .add_attribute("action", "counter_offer")
+        .add_attribute("token_id", token_id)
+        .add_attribute("nft_address", nft_address)
+        .add_attribute("bidder", counter_offer.bidder)
+        .add_attribute("price", format!("{}", counter_offer.price))
+        .add_attribute("expire_at", counter_offer.expire_at.to_string())
+    )
+}
 
-    let mut messages: Vec<CosmosMsg> = vec![];
+/**
+ * @dev Accept a counter-offer by sending the difference to the escrowed bid,
+ *  the sale settles right away at the counter price
+ */
+fn _accept_counter_offer(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    token_id: String,
+    nft_address: String,
+    price: Asset
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    let counter_offer = COUNTER_OFFERS.may_load(deps.storage, (&token_id, &nft_address))?
+        .ok_or(ContractError::NoCounterOffer {})?;
+    if counter_offer.bidder != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    if counter_offer.expire_at.is_expired(&env.block) {
+        return Err(ContractError::Expired {});
+    }
+    if counter_offer.price != price {
+        return Err(ContractError::InvalidPrice {});
+    }
+    let order = orders().may_load(deps.storage, (&token_id, &nft_address))?
+        .ok_or(ContractError::NoOrder {})?;
+    if order.expire_at.is_expired(&env.block) {
+        return Err(ContractError::Expired {});
+    }
+    let mut bid = bids().may_load(deps.storage, (&token_id, &nft_address))?
+        .ok_or(ContractError::NoBid {})?;
 
-    // transfer escrowed bid amount minus market fee to seller
-    let seller_amount_asset = Asset {
+    // the top-up joins the escrowed bid
+    Asset {
         info: bid.price.info.clone(),
-        amount: bid.price.amount - (order.price.amount * con.owner_cut_rate)
-    };
-    messages.push(seller_amount_asset.into_msg(&deps.querier, order.seller.clone())?);
+        amount: counter_offer.price.amount.checked_sub(bid.price.amount)?
+    }.assert_sent_native_token_balance(&info)?;
+    bid.price = counter_offer.price;
+
+    let con = CONFIG.load(deps.storage)?;
+    let messages = execute_bid_sale(deps, &con, &order, &bid, SaleType::CounterOffer, &env.block)?;
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attribute("action", "accept_counter_offer")
+        .add_attribute("token_id", order.token_id)
+        .add_attribute("nft_address", order.nft_address)
+        .add_attribute("seller", order.seller)
+        .add_attribute("bidder", bid.bidder)
+        .add_attribute("price", format!("{}", bid.price))
+    )
+}
+
+/**
+ * @dev Settle a sale to the bidder out of their escrowed bid
+ */
+fn execute_bid_sale(
+    deps: DepsMut,
+    con: &Config,
+    order: &Order,
+    bid: &Bid,
+    sale_type: SaleType,
+    block: &BlockInfo
+) -> Result<Vec<CosmosMsg<TerraMsgWrapper>>, ContractError> {
+    let token_id = order.token_id.clone();
+    let nft_address = order.nft_address.to_string();
+    assert_token_not_blocked(deps.storage, &token_id, &nft_address)?;
+    assert_not_blocked(deps.storage, &[&order.seller, &bid.bidder])?;
+    let now = block.time.seconds();
+    // transfer escrowed bid amount minus market fee to seller,
+    // the escrow is all there is so the tax always comes out of the payouts
+    let royalties = collection_royalties(deps.storage, &[&order.nft_address], &bid.price)?;
+    let settlement = compute_settlement(&deps.querier, fee_rate(deps.storage, con, &[&order.nft_address], &order.seller, &bid.price.info)?, &bid.price, &order.seller, royalties, order.payout_swap.as_ref(), false)?;
+    record_sale(deps.storage, &order.nft_address, &token_id, &order.seller, &bid.bidder, &settlement.breakdown, sale_type, block)?;
+    let mut messages = settlement.into_messages(deps.storage)?;
 
     // send nft to bidder
     messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
-        contract_addr: order.nft_address.to_string(),
+        contract_addr: nft_address.clone(),
         msg: to_binary(&Cw721ExecuteMsg::TransferNft {
-          recipient: bid.bidder.to_string(), 
-          token_id: order.token_id.clone()
+          recipient: bid.bidder.to_string(),
+          token_id: token_id.clone()
         })?,
         funds: vec![]
       })
     );
+    record_negotiation(deps.storage, &token_id, &nft_address, NegotiationAction::Accepted, &order.seller, &bid.price, now)?;
+    // remove bids and orders
+    remove_bid(deps.storage, &token_id, &nft_address)?;
+    COUNTER_OFFERS.remove(deps.storage, (&token_id, &nft_address));
+    remove_order(deps.storage, &token_id, &nft_address)?;
+    Ok(messages)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn record_sale(
+    storage: &mut dyn Storage,
+    nft_address: &Addr,
+    token_id: &str,
+    seller: &Addr,
+    buyer: &Addr,
+    breakdown: &SettlementResponse,
+    sale_type: SaleType,
+    block: &BlockInfo
+) -> StdResult<Sale> {
+    let sale_id = SALE_COUNT.may_load(storage)?.unwrap_or_default() + 1;
+    SALE_COUNT.save(storage, &sale_id)?;
+    let sale = Sale {
+        sale_id,
+        nft_address: nft_address.clone(),
+        token_id: token_id.to_string(),
+        seller: seller.clone(),
+        buyer: buyer.clone(),
+        price: breakdown.price.clone(),
+        marketplace_fee: breakdown.marketplace_fee,
+        royalty: breakdown.royalty,
+        sale_type,
+        time: block.time.seconds(),
+        height: block.height
+    };
+    sales().save(storage, sale_id.into(), &sale)?;
+
+    let mut stats = COLLECTION_STATS.may_load(storage, nft_address.as_str())?.unwrap_or_default();
+    stats.sales_count += 1;
+    stats.last_sale = Some(sale.price.clone());
+    COLLECTION_STATS.save(storage, nft_address.as_str(), &stats)?;
+    let volume_key = (nft_address.as_bytes(), sale.price.info.as_bytes());
+    let mut volume = collection_volumes().may_load(storage, volume_key)?.unwrap_or(CollectionVolume {
+        nft_address: nft_address.clone(),
+        volume: Asset { info: sale.price.info.clone(), amount: Uint128::zero() }
+    });
+    volume.volume.amount = volume.volume.amount.checked_add(sale.price.amount)?;
+    collection_volumes().save(storage, volume_key, &volume)?;
+    record_user_trade(storage, &sale)?;
+    // bundle shares aren't prices anyone agreed on, keep them off the price curve
+    if !matches!(sale.sale_type, SaleType::Bundle { .. }) {
+        record_price_observation(storage, &sale)?;
+    }
+    Ok(sale)
+}
+
+/// Count a sale in the trading stats of its buyer and seller
+fn record_user_trade(storage: &mut dyn Storage, sale: &Sale) -> StdResult<()> {
+    let mut buyer_stats = USER_STATS.may_load(storage, sale.buyer.as_str())?.unwrap_or_default();
+    buyer_stats.items_bought += 1;
+    USER_STATS.save(storage, sale.buyer.as_str(), &buyer_stats)?;
+    let mut seller_stats = USER_STATS.may_load(storage, sale.seller.as_str())?.unwrap_or_default();
+    seller_stats.items_sold += 1;
+    USER_STATS.save(storage, sale.seller.as_str(), &seller_stats)?;
+
+    let load = |storage: &dyn Storage, address: &Addr| -> StdResult<UserVolume> {
+        Ok(user_volumes().may_load(storage, (address.as_bytes(), sale.price.info.as_bytes()))?.unwrap_or(UserVolume {
+            address: address.clone(),
+            info: sale.price.info.clone(),
+            bought: Uint128::zero(),
+            sold: Uint128::zero(),
+            fees_paid: Uint128::zero(),
+            royalties_paid: Uint128::zero()
+        }))
+    };
+    let mut buyer = load(storage, &sale.buyer)?;
+    buyer.bought = buyer.bought.checked_add(sale.price.amount)?;
+    user_volumes().save(storage, (sale.buyer.as_bytes(), sale.price.info.as_bytes()), &buyer)?;
+    let mut seller = load(storage, &sale.seller)?;
+    seller.sold = seller.sold.checked_add(sale.price.amount)?;
+    seller.fees_paid = seller.fees_paid.checked_add(sale.marketplace_fee)?;
+    seller.royalties_paid = seller.royalties_paid.checked_add(sale.royalty)?;
+    user_volumes().save(storage, (sale.seller.as_bytes(), sale.price.info.as_bytes()), &seller)
+}
+
+/// Largest move, in percent of the previous observation, a single sale can make the price curve take
+const TWAP_MAX_DEVIATION: u64 = 20;
+
+/**
+ * @dev Add a sale to its collection's price curve. The price is clamped to
+ *  within TWAP_MAX_DEVIATION of the previous observation so that a wash trade
+ *  at an absurd price only nudges the average, real moves take a few sales.
+ */
+fn record_price_observation(storage: &mut dyn Storage, sale: &Sale) -> StdResult<()> {
+    let (nft_address, asset) = (sale.nft_address.as_bytes(), sale.price.info.as_bytes());
+    // several sales in the same block leave one observation, clamped against the block before
+    let last = PRICE_OBSERVATIONS
+        .prefix((nft_address, asset))
+        .range(storage, None, Some(Bound::exclusive(U64Key::new(sale.time).joined_key())), CwOrder::Descending)
+        .next()
+        .transpose()?;
+    let observation = match last {
+        Some((_, last)) => {
+            let low = last.price.multiply_ratio(100 - TWAP_MAX_DEVIATION, 100u64);
+            let high = last.price.multiply_ratio(100 + TWAP_MAX_DEVIATION, 100u64);
+            PriceObservation {
+                time: sale.time,
+                price: sale.price.amount.max(low).min(high),
+                cumulative: accumulate(&last, sale.time)?
+            }
+        }
+        None => PriceObservation { time: sale.time, price: sale.price.amount, cumulative: Uint128::zero() }
+    };
+    PRICE_OBSERVATIONS.save(storage, (nft_address, asset, U64Key::new(sale.time)), &observation)
+}
+
+/// Save a listing, counting it in the collection stats if it's new
+fn save_order(storage: &mut dyn Storage, order: &Order) -> StdResult<()> {
+    let key = (order.token_id.as_str(), order.nft_address.as_str());
+    if orders().may_load(storage, key)?.is_none() {
+        let mut stats = COLLECTION_STATS.may_load(storage, key.1)?.unwrap_or_default();
+        stats.listed_count += 1;
+        COLLECTION_STATS.save(storage, key.1, &stats)?;
+    }
+    orders().save(storage, key, order)
+}
+
+/// Remove a listing, if any, and uncount it from the collection stats
+fn remove_order(storage: &mut dyn Storage, token_id: &str, nft_address: &str) -> StdResult<()> {
+    if orders().may_load(storage, (token_id, nft_address))?.is_some() {
+        let mut stats = COLLECTION_STATS.may_load(storage, nft_address)?.unwrap_or_default();
+        stats.listed_count = stats.listed_count.saturating_sub(1);
+        COLLECTION_STATS.save(storage, nft_address, &stats)?;
+        orders().remove(storage, (token_id, nft_address))?;
+    }
+    Ok(())
+}
+
+/// Record a bundle sale per NFT, splitting the amounts evenly with the remainder on the first one
+fn record_bundle_sale(
+    storage: &mut dyn Storage,
+    bundle: &Bundle,
+    buyer: &Addr,
+    breakdown: &SettlementResponse,
+    block: &BlockInfo
+) -> StdResult<()> {
+    let count = Uint128::from(bundle.nfts.len() as u128);
+    let share = |amount: Uint128, first: bool| {
+        let part = amount.multiply_ratio(1u128, count);
+        if first { amount - part * (count - Uint128::from(1u128)) } else { part }
+    };
+    for (i, item) in bundle.nfts.iter().enumerate() {
+        let first = i == 0;
+        let item_breakdown = SettlementResponse {
+            price: Asset { info: breakdown.price.info.clone(), amount: share(breakdown.price.amount, first) },
+            marketplace_fee: share(breakdown.marketplace_fee, first),
+            royalty: share(breakdown.royalty, first),
+            ..breakdown.clone()
+        };
+        record_sale(storage, &item.nft_address, &item.token_id, &bundle.seller, buyer, &item_breakdown,
+            SaleType::Bundle { bundle_id: bundle.bundle_id }, block)?;
+    }
+    Ok(())
+}
+
+fn record_negotiation(
+    storage: &mut dyn Storage,
+    token_id: &str,
+    nft_address: &str,
+    action: NegotiationAction,
+    from: &Addr,
+    price: &Asset,
+    at: u64
+) -> StdResult<()> {
+    NEGOTIATIONS.update(storage, (token_id, nft_address), |entries| -> StdResult<_> {
+        let mut entries = entries.unwrap_or_default();
+        entries.push(NegotiationEntry { action, from: from.clone(), price: price.clone(), at });
+        Ok(entries)
+    })?;
+    Ok(())
+}
+
+fn _cancel_bid(
+    storage: &mut dyn Storage,
+    querier: &QuerierWrapper,
+    token_id: String,
+    nft_address: String
+) -> Result<Option<CosmosMsg<TerraMsgWrapper>>, ContractError> {
+    let bid = bids().may_load(storage, (&token_id, &nft_address))?
+        .ok_or(ContractError::NoBid {})?;
+    let refund = payout(storage, querier, bid.price, bid.bidder)?;
+    remove_bid(storage, &token_id, &nft_address)?;
+    COUNTER_OFFERS.remove(storage, (&token_id, &nft_address));
+    Ok(refund)
+}
+
+fn _safe_execute_order(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    token_id: String,
+    nft_address: String,
+    price: Asset
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+
+    let order = orders().may_load(deps.storage, (&token_id, &nft_address))?
+        .ok_or(ContractError::NoOrder {})?;
+    if order.price.info != price.info || order.price.amount != price.amount {
+        return Err(ContractError::InvalidPrice {});
+    }
+    if order.seller == info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    if order.expire_at.is_expired(&env.block) {
+        return Err(ContractError::Expired {});
+    }
+    assert_not_reserved(&order, &info.sender)?;
+    // Transfer all amount by coin param on calling
+    // it should be performed from frontend by coin params.
+    let con = CONFIG.load(deps.storage)?;
+    let settlement = compute_settlement(&deps.querier, fee_rate(deps.storage, &con, &[&order.nft_address], &order.seller, &order.price.info)?, &order.price, &order.seller,
+            collection_royalties(deps.storage, &[&order.nft_address], &order.price)?, order.payout_swap.as_ref(), con.buyer_pays_tax)?;
+    Asset {
+        info: order.price.info.clone(),
+        amount: settlement.breakdown.buyer_total
+    }.assert_sent_native_token_balance(&info)?;
+    let messages = execute_sale(deps, &order, settlement, &info.sender, &env.block)?;
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attribute("action", "_safe_execute_order")
+        .add_attribute("token_id", token_id)
+        .add_attribute("nft_address", nft_address)
+    )
+}
+
+/// Expirations must fall within the configured listing durations
+fn assert_valid_expiration(con: &Config, block: &BlockInfo, expiration: &Expiration) -> Result<(), ContractError> {
+    let durations = &con.listing_durations;
+    let (now, at, min, max) = match expiration {
+        Expiration::AtTime(time) => (block.time.seconds(), time.seconds(), durations.min_time, durations.max_time),
+        Expiration::AtHeight(height) => (block.height, *height, durations.min_height, durations.max_height),
+        Expiration::Never {} => {
+            if durations.max_time.is_some() || durations.max_height.is_some() {
+                return Err(ContractError::InvalidExpiration {});
+            }
+            return Ok(());
+        }
+    };
+    if at < now.saturating_add(min) || matches!(max, Some(max) if at > now.saturating_add(max)) {
+        return Err(ContractError::InvalidExpiration {});
+    }
+    Ok(())
+}
+
+/// Private listings can only be bought or bid on by the address they are reserved for
+fn assert_not_reserved(order: &Order, buyer: &Addr) -> Result<(), ContractError> {
+    match &order.reserved_for {
+        Some(reserved_for) if reserved_for != buyer => Err(ContractError::ReservedOrder {}),
+        _ => Ok(())
+    }
+}
+
+/**
+ * @dev Pay out a settled sale, refund a pending bid and hand the NFT to the buyer.
+ *  The buyer's payment has to be checked by the caller.
+ */
+fn execute_sale(
+    deps: DepsMut,
+    order: &Order,
+    settlement: Settlement,
+    buyer: &Addr,
+    block: &BlockInfo
+) -> Result<Vec<CosmosMsg<TerraMsgWrapper>>, ContractError> {
+    let token_id = order.token_id.clone();
+    let nft_address = order.nft_address.to_string();
+    assert_token_not_blocked(deps.storage, &token_id, &nft_address)?;
+    assert_not_blocked(deps.storage, &[&order.seller, buyer])?;
+    record_sale(deps.storage, &order.nft_address, &token_id, &order.seller, buyer, &settlement.breakdown, SaleType::Order, block)?;
+    let mut messages = settlement.into_messages(deps.storage)?;
+
     // remove bids and orders
-    BIDS.remove(deps.storage, (&token_id, &nft_address));
-    ORDERS.remove(deps.storage, (&token_id, &nft_address));
+    if bids().may_load(deps.storage, (&token_id, &nft_address))?.is_some() {
+        messages.extend(_cancel_bid(deps.storage, &deps.querier, token_id.clone(), nft_address.clone())?);
+    }
+    messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: nft_address.clone(),
+        msg: to_binary(&Cw721ExecuteMsg::TransferNft {
+          recipient: buyer.to_string(),
+          token_id: token_id.clone()
+        })?,
+        funds: vec![]
+      })
+    );
+    remove_order(deps.storage, &token_id, &nft_address)?;
+    Ok(messages)
+}
+
+fn _accept_bid(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    token_id: String,
+    nft_address: String,
+    price: Asset
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+
+    let con = CONFIG.load(deps.storage)?;
+
+    //Frontend -  send coin amount as param
+    let order = orders().may_load(deps.storage, (&token_id, &nft_address))?
+        .ok_or(ContractError::NoOrder {})?;
+    // only seller approve order
+    if order.seller != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    if order.expire_at.is_expired(&env.block) {
+        return Err(ContractError::Expired {})
+    }
+
+    let bid = bids().may_load(deps.storage, (&token_id, &nft_address))?
+        .ok_or(ContractError::NoBid {})?;
+
+    if bid.price.info != price.info || bid.price.amount != price.amount {
+        return Err(ContractError::InvalidPrice {});
+    }
+    if bid.expire_at.is_expired(&env.block) {
+        return Err(ContractError::BidExpired {})
+    }
+
+    let messages = execute_bid_sale(deps, &con, &order, &bid, SaleType::Bid, &env.block)?;
     Ok(Response::new()
         .add_messages(messages)
         .add_attribute("action", "accept_order")
@@ -535,95 +1758,2894 @@ fn _accept_bid(
     )
 }
 
-#[cfg_attr(not(feature = "library"), entry_point)]
-pub fn migrate(_deps: DepsMut, _env: Env, _msg: MigrateMsg) -> StdResult<Response> {
-    Ok(Response::default())
+pub fn create_bundle(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    nfts: Vec<NftItemMsg>,
+    price: Asset,
+    expire_at: Expiration,
+    payout_swap: Option<PayoutSwap>
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    let nft_addresses: Vec<&str> = nfts.iter().map(|item| item.nft_address.as_str()).collect();
+    assert_not_paused(deps.storage, Operation::Listing, &nft_addresses)?;
+    for item in nfts.iter() {
+        assert_accepted_asset(deps.storage, &item.nft_address, &price.info)?;
+        let owner = query_nft_owner(&deps.querier, &item.nft_address, &item.token_id)?;
+        if owner != info.sender {
+            return Err(ContractError::NoOwner {})
+        }
+    }
+    _create_bundle(deps, env, info, nfts, price, expire_at, payout_swap)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
-    use crate::msg::{ InstantiateMsg };
-    use crate::asset::{ Asset, AssetInfo };
-    use cosmwasm_std::{ Timestamp };
+pub fn cancel_bundle(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    bundle_id: u64
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    assert_not_paused(deps.storage, Operation::Cancelling, &[])?;
+    _cancel_bundle(deps, env, info, bundle_id)
+}
 
-    mod instantiate {
-        use super::*;
+pub fn buy_bundle(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    bundle_id: u64,
+    price: Asset
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    let bundle_nfts = BUNDLES.may_load(deps.storage, bundle_id.into())?.map(|b| b.nfts).unwrap_or_default();
+    let nft_addresses: Vec<&str> = bundle_nfts.iter().map(|item| item.nft_address.as_str()).collect();
+    assert_not_paused(deps.storage, Operation::Buying, &nft_addresses)?;
+    _buy_bundle(deps, env, info, bundle_id, price)
+}
 
-        #[test]
-        fn works() {
-            let mut deps = mock_dependencies(&[]);
-            let instantiate_msg = InstantiateMsg {
-                decimals: Uint128::from(11223344u128),
-                name: "testing2".to_string(),
-                symbol: "testing".to_string(),
-                accepted_token: "terra1kc87mu460fwkqte29rquh4hc20m54fxwtsx7gp".to_string(),
-                owner_cut_rate: Decimal::from_ratio(Uint128::from(10u64), Uint128::from(100u64))
-            };
-            let res = instantiate(deps.as_mut(), mock_env(), mock_info(&"signer".to_string(), &[]), instantiate_msg).unwrap();
-            assert_eq!(0, res.messages.len());
+/**
+ * @dev List several NFTs, possibly from different collections, sold as one unit.
+ *  Like single orders, the NFTs are escrowed by the frontend right after listing.
+ */
+fn _create_bundle(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    nfts: Vec<NftItemMsg>,
+    price: Asset,
+    expire_at: Expiration,
+    payout_swap: Option<PayoutSwap>
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    if price.amount <= Uint128::zero() {
+        return Err(ContractError::InvalidPrice {})
+    }
+    let con = CONFIG.load(deps.storage)?;
+    assert_valid_expiration(&con, &env.block, &expire_at)?;
+    assert_not_blocked(deps.storage, &[&info.sender])?;
+    assert_valid_payout_swap(deps.storage, &info.sender, &price, &payout_swap)?;
 
+    let mut items: Vec<BundleItem> = vec![];
+    for item in nfts {
+        if orders().may_load(deps.storage, (&item.token_id, &item.nft_address))?.is_some() {
+            return Err(ContractError::InvalidBundle {});
+        }
+        assert_listable(deps.storage, &con, &item.nft_address)?;
+        assert_token_not_blocked(deps.storage, &item.token_id, &item.nft_address)?;
+        let item = BundleItem {
+            token_id: item.token_id,
+            nft_address: deps.api.addr_validate(&item.nft_address)?
+        };
+        if items.contains(&item) {
+            return Err(ContractError::InvalidBundle {});
         }
+        items.push(item);
+    }
+    if items.is_empty() {
+        return Err(ContractError::InvalidBundle {});
     }
 
-    #[test]
-    fn create_order_works() {
-        let mut deps = mock_dependencies(&[]);
-        let info = mock_info(&"signer".to_string(), &[]);
-        let env = mock_env();
-        let expiration = 1648958996u64;
-        let expired_expiration = env.block.time.seconds();
+    let bundle_id = BUNDLE_COUNT.may_load(deps.storage)?.unwrap_or_default() + 1;
+    BUNDLE_COUNT.save(deps.storage, &bundle_id)?;
+    let bundle = Bundle {
+        bundle_id,
+        seller: info.sender,
+        nfts: items,
+        price,
+        expire_at,
+        payout_swap
+    };
+    BUNDLES.save(deps.storage, bundle_id.into(), &bundle)?;
+    Ok(Response::new()
+        .add_attribute("action", "create_bundle")
+        .add_attribute("bundle_id", bundle_id.to_string())
+        .add_attribute("seller", bundle.seller)
+        .add_attribute("size", bundle.nfts.len().to_string())
+        .add_attribute("expire_at", bundle.expire_at.to_string())
+        .add_attribute("price", bundle.price.amount)
+    )
+}
 
-        let price = Asset {
-            amount: Uint128::from(10000u128),
-            info: AssetInfo::NativeToken {denom : "uluna".to_string()}
-        };
-        let zeroprice = Asset {
+fn _cancel_bundle(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    bundle_id: u64
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    let bundle = BUNDLES.may_load(deps.storage, bundle_id.into())?
+        .ok_or(ContractError::NoBundle {})?;
+    // only seller cancel bundle
+    if bundle.seller != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    //  send assets back to seller
+    let messages = nft_transfer_messages(&bundle.nfts, &bundle.seller)?;
+    BUNDLES.remove(deps.storage, bundle_id.into());
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attribute("action", "cancel_bundle")
+        .add_attribute("bundle_id", bundle_id.to_string())
+    )
+}
+
+fn _buy_bundle(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    bundle_id: u64,
+    price: Asset
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    let bundle = BUNDLES.may_load(deps.storage, bundle_id.into())?
+        .ok_or(ContractError::NoBundle {})?;
+    if bundle.price != price {
+        return Err(ContractError::InvalidPrice {});
+    }
+    if bundle.seller == info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    if bundle.expire_at.is_expired(&env.block) {
+        return Err(ContractError::Expired {});
+    }
+
+    assert_not_blocked(deps.storage, &[&bundle.seller, &info.sender])?;
+    for item in bundle.nfts.iter() {
+        assert_token_not_blocked(deps.storage, &item.token_id, item.nft_address.as_str())?;
+    }
+    let con = CONFIG.load(deps.storage)?;
+    let royalties = bundle_royalties(deps.storage, &bundle)?;
+    let settlement = compute_settlement(&deps.querier, bundle_fee_rate(deps.storage, &con, &bundle)?, &bundle.price, &bundle.seller, royalties, bundle.payout_swap.as_ref(), con.buyer_pays_tax)?;
+    Asset {
+        info: bundle.price.info.clone(),
+        amount: settlement.breakdown.buyer_total
+    }.assert_sent_native_token_balance(&info)?;
+    record_bundle_sale(deps.storage, &bundle, &info.sender, &settlement.breakdown, &env.block)?;
+    let mut messages = settlement.into_messages(deps.storage)?;
+    messages.extend(nft_transfer_messages(&bundle.nfts, &info.sender)?);
+    BUNDLES.remove(deps.storage, bundle_id.into());
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attribute("action", "buy_bundle")
+        .add_attribute("bundle_id", bundle_id.to_string())
+        .add_attribute("seller", bundle.seller)
+        .add_attribute("buyer", info.sender)
+        .add_attribute("price", format!("{}", bundle.price))
+    )
+}
+
+fn nft_transfer_messages(nfts: &[BundleItem], recipient: &Addr) -> StdResult<Vec<CosmosMsg<TerraMsgWrapper>>> {
+    nfts.iter().map(|item| Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: item.nft_address.to_string(),
+        msg: to_binary(&Cw721ExecuteMsg::TransferNft {
+          recipient: recipient.to_string(),
+          token_id: item.token_id.clone()
+        })?,
+        funds: vec![]
+    }))).collect()
+}
+
+pub fn create_swap(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    offered: Vec<NftItemMsg>,
+    sweetener: Option<Asset>,
+    wanted: SwapWantMsg,
+    expire_at: Expiration
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    let nft_addresses: Vec<&str> = offered.iter().map(|item| item.nft_address.as_str()).collect();
+    assert_not_paused(deps.storage, Operation::Listing, &nft_addresses)?;
+    for item in offered.iter() {
+        let owner = query_nft_owner(&deps.querier, &item.nft_address, &item.token_id)?;
+        if owner != info.sender {
+            return Err(ContractError::NoOwner {})
+        }
+    }
+    _create_swap(deps, env, info, offered, sweetener, wanted, expire_at)
+}
+
+pub fn cancel_swap(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    swap_id: u64
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    assert_not_paused(deps.storage, Operation::Cancelling, &[])?;
+    _cancel_swap(deps, env, info, swap_id)
+}
+
+pub fn fill_swap(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    swap_id: u64,
+    nfts: Vec<NftItemMsg>
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    let nft_addresses: Vec<&str> = nfts.iter().map(|item| item.nft_address.as_str()).collect();
+    assert_not_paused(deps.storage, Operation::Accepting, &nft_addresses)?;
+    for item in nfts.iter() {
+        let owner = query_nft_owner(&deps.querier, &item.nft_address, &item.token_id)?;
+        if owner != info.sender {
+            return Err(ContractError::NoOwner {})
+        }
+    }
+    _fill_swap(deps, env, info, swap_id, nfts)
+}
+
+/// Validate a set of distinct NFTs that aren't listed on their own
+fn swap_items(deps: Deps, nfts: Vec<NftItemMsg>) -> Result<Vec<BundleItem>, ContractError> {
+    let mut items: Vec<BundleItem> = vec![];
+    for item in nfts {
+        if orders().may_load(deps.storage, (&item.token_id, &item.nft_address))?.is_some() {
+            return Err(ContractError::InvalidSwap {});
+        }
+        let item = BundleItem {
+            token_id: item.token_id,
+            nft_address: deps.api.addr_validate(&item.nft_address)?
+        };
+        if items.contains(&item) {
+            return Err(ContractError::InvalidSwap {});
+        }
+        items.push(item);
+    }
+    if items.is_empty() {
+        return Err(ContractError::InvalidSwap {});
+    }
+    Ok(items)
+}
+
+/**
+ * @dev Offer NFTs for a given set of NFTs or any few of a collection.
+ *  The offered NFTs are escrowed by the frontend right after creation,
+ *  the sweetener is escrowed with the message.
+ */
+fn _create_swap(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    offered: Vec<NftItemMsg>,
+    sweetener: Option<Asset>,
+    wanted: SwapWantMsg,
+    expire_at: Expiration
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    let con = CONFIG.load(deps.storage)?;
+    assert_valid_expiration(&con, &env.block, &expire_at)?;
+    assert_not_blocked(deps.storage, &[&info.sender])?;
+    let offered = swap_items(deps.as_ref(), offered)?;
+    for item in offered.iter() {
+        assert_listable(deps.storage, &con, item.nft_address.as_str())?;
+        assert_token_not_blocked(deps.storage, &item.token_id, item.nft_address.as_str())?;
+    }
+    let wanted = match wanted {
+        SwapWantMsg::Nfts { nfts } => {
+            let nfts = swap_items(deps.as_ref(), nfts)?;
+            if nfts.iter().any(|item| offered.contains(item)) {
+                return Err(ContractError::InvalidSwap {});
+            }
+            SwapWant::Nfts { nfts }
+        }
+        SwapWantMsg::AnyOf { nft_address, count } => {
+            if count == 0 {
+                return Err(ContractError::InvalidSwap {});
+            }
+            SwapWant::AnyOf { nft_address: deps.api.addr_validate(&nft_address)?, count }
+        }
+    };
+    if let Some(sweetener) = &sweetener {
+        // only native sweeteners can be escrowed with the message
+        if !sweetener.info.is_native_token() || sweetener.amount.is_zero() {
+            return Err(ContractError::InvalidPrice {});
+        }
+        sweetener.assert_sent_native_token_balance(&info)?;
+    }
+
+    let swap_id = SWAP_COUNT.may_load(deps.storage)?.unwrap_or_default() + 1;
+    SWAP_COUNT.save(deps.storage, &swap_id)?;
+    let swap = Swap {
+        swap_id,
+        maker: info.sender,
+        offered,
+        sweetener,
+        wanted,
+        expire_at
+    };
+    if let Some(sweetener) = &swap.sweetener {
+        add_to_total(deps.storage, &ESCROW, sweetener)?;
+    }
+    SWAPS.save(deps.storage, swap_id.into(), &swap)?;
+    Ok(Response::new()
+        .add_attribute("action", "create_swap")
+        .add_attribute("swap_id", swap_id.to_string())
+        .add_attribute("maker", swap.maker)
+        .add_attribute("size", swap.offered.len().to_string())
+        .add_attribute("expire_at", swap.expire_at.to_string())
+    )
+}
+
+fn _cancel_swap(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    swap_id: u64
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    let swap = SWAPS.may_load(deps.storage, swap_id.into())?
+        .ok_or(ContractError::NoSwap {})?;
+    // only maker cancel swap
+    if swap.maker != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    //  send escrowed NFTs and sweetener back to the maker
+    let mut messages = nft_transfer_messages(&swap.offered, &swap.maker)?;
+    if let Some(sweetener) = swap.sweetener.clone() {
+        messages.extend(payout(deps.storage, &deps.querier, sweetener, swap.maker.clone())?);
+    }
+    remove_swap(deps.storage, &swap)?;
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attribute("action", "cancel_swap")
+        .add_attribute("swap_id", swap_id.to_string())
+    )
+}
+
+/**
+ * @dev Fill a swap with the taker's NFTs. Both sides are transferred in the same
+ *  transaction, the taker's NFTs through the marketplace's cw721 approval.
+ */
+fn _fill_swap(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    swap_id: u64,
+    nfts: Vec<NftItemMsg>
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    let swap = SWAPS.may_load(deps.storage, swap_id.into())?
+        .ok_or(ContractError::NoSwap {})?;
+    if swap.maker == info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    if swap.expire_at.is_expired(&env.block) {
+        return Err(ContractError::Expired {});
+    }
+    assert_not_blocked(deps.storage, &[&swap.maker, &info.sender])?;
+    let given = swap_items(deps.as_ref(), nfts)?;
+    for item in swap.offered.iter().chain(given.iter()) {
+        assert_token_not_blocked(deps.storage, &item.token_id, item.nft_address.as_str())?;
+    }
+    let matches = match &swap.wanted {
+        SwapWant::Nfts { nfts } => given.len() == nfts.len() && given.iter().all(|item| nfts.contains(item)),
+        SwapWant::AnyOf { nft_address, count } => given.len() == *count as usize
+            && given.iter().all(|item| item.nft_address == *nft_address)
+    };
+    if !matches {
+        return Err(ContractError::InvalidSwap {});
+    }
+
+    let mut messages = nft_transfer_messages(&given, &swap.maker)?;
+    messages.extend(nft_transfer_messages(&swap.offered, &info.sender)?);
+    if let Some(sweetener) = swap.sweetener.clone() {
+        messages.extend(payout(deps.storage, &deps.querier, sweetener, info.sender.clone())?);
+    }
+    remove_swap(deps.storage, &swap)?;
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attribute("action", "fill_swap")
+        .add_attribute("swap_id", swap_id.to_string())
+        .add_attribute("maker", swap.maker)
+        .add_attribute("taker", info.sender)
+    )
+}
+
+pub fn emergency_withdraw(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    limit: Option<u32>
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    assert_not_paused(deps.storage, Operation::Cancelling, &[])?;
+    if !CONFIG.load(deps.storage)?.withdrawal_mode {
+        return Err(ContractError::NotInWithdrawalMode {});
+    }
+    _emergency_withdraw(deps, env, info, limit)
+}
+
+/**
+ * @dev Give escrow back to its owners: listed NFTs and bundles to their sellers,
+ *  bids to their bidders, swap NFTs and sweeteners to their makers.
+ *  NFTs always go back. Funds in an asset the contract holds too little of are
+ *  credited to their owners instead of paid out, so an insolvent asset isn't
+ *  drained first come first served and the rest of the escrow still unwinds.
+ */
+fn _emergency_withdraw(
+    deps: DepsMut,
+    env: Env,
+    _info: MessageInfo,
+    limit: Option<u32>
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    let short = short_assets(deps.as_ref(), &env)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let mut messages: Vec<CosmosMsg<TerraMsgWrapper>> = vec![];
+
+    let listed = orders().range(deps.storage, None, None, CwOrder::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, order)| order))
+        .collect::<StdResult<Vec<Order>>>()?;
+    for order in listed.iter() {
+        let key = (order.token_id.as_str(), order.nft_address.as_str());
+        if let Some(bid) = bids().may_load(deps.storage, key)? {
+            messages.extend(return_bid(deps.storage, &deps.querier, bid, &short)?);
+        }
+        messages.extend(nft_transfer_messages(
+            &[BundleItem { token_id: order.token_id.clone(), nft_address: order.nft_address.clone() }],
+            &order.seller
+        )?);
+        remove_order(deps.storage, key.0, key.1)?;
+    }
+
+    let orphan_bids = bids().range(deps.storage, None, None, CwOrder::Ascending)
+        .take(limit - listed.len())
+        .map(|item| item.map(|(_, bid)| bid))
+        .collect::<StdResult<Vec<Bid>>>()?;
+    for bid in orphan_bids.iter() {
+        messages.extend(return_bid(deps.storage, &deps.querier, bid.clone(), &short)?);
+    }
+
+    let bundles = BUNDLES.range(deps.storage, None, None, CwOrder::Ascending)
+        .take(limit - listed.len() - orphan_bids.len())
+        .map(|item| item.map(|(_, bundle)| bundle))
+        .collect::<StdResult<Vec<Bundle>>>()?;
+    for bundle in bundles.iter() {
+        messages.extend(nft_transfer_messages(&bundle.nfts, &bundle.seller)?);
+        BUNDLES.remove(deps.storage, bundle.bundle_id.into());
+    }
+
+    let swaps = SWAPS.range(deps.storage, None, None, CwOrder::Ascending)
+        .take(limit - listed.len() - orphan_bids.len() - bundles.len())
+        .map(|item| item.map(|(_, swap)| swap))
+        .collect::<StdResult<Vec<Swap>>>()?;
+    for swap in swaps.iter() {
+        messages.extend(nft_transfer_messages(&swap.offered, &swap.maker)?);
+        if let Some(sweetener) = swap.sweetener.clone() {
+            messages.extend(refund_unless_short(deps.storage, &deps.querier, sweetener, swap.maker.clone(), &short)?);
+        }
+        remove_swap(deps.storage, swap)?;
+    }
+
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attribute("action", "emergency_withdraw")
+        .add_attribute("orders", listed.len().to_string())
+        .add_attribute("bids", orphan_bids.len().to_string())
+        .add_attribute("bundles", bundles.len().to_string())
+        .add_attribute("swaps", swaps.len().to_string())
+        .add_attribute("short", join_asset_infos(&short))
+    )
+}
+
+/// Assets the contract holds less of than it owes in escrow and fees
+fn short_assets(deps: Deps, env: &Env) -> StdResult<Vec<AssetInfo>> {
+    Ok(query_escrow_balances(deps, env.clone())?.balances.into_iter()
+        .filter(|b| b.escrowed.checked_add(b.fees).map_or(true, |owed| b.held < owed))
+        .map(|b| b.info)
+        .collect())
+}
+
+/// Pay escrow back to its owner, or credit it to them while its asset is short
+fn refund_unless_short(
+    storage: &mut dyn Storage,
+    querier: &QuerierWrapper,
+    asset: Asset,
+    recipient: Addr,
+    short: &[AssetInfo]
+) -> Result<Option<CosmosMsg<TerraMsgWrapper>>, ContractError> {
+    if short.contains(&asset.info) {
+        credit(storage, &recipient, &asset)?;
+        return Ok(None);
+    }
+    payout(storage, querier, asset, recipient)
+}
+
+fn return_bid(
+    storage: &mut dyn Storage,
+    querier: &QuerierWrapper,
+    bid: Bid,
+    short: &[AssetInfo]
+) -> Result<Option<CosmosMsg<TerraMsgWrapper>>, ContractError> {
+    let refund = refund_unless_short(storage, querier, bid.price, bid.bidder, short)?;
+    remove_bid(storage, &bid.token_id, bid.nft_address.as_str())?;
+    COUNTER_OFFERS.remove(storage, (&bid.token_id, bid.nft_address.as_str()));
+    Ok(refund)
+}
+
+pub fn set_payout_mode(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    mode: PayoutMode
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    match mode {
+        PayoutMode::Push => PAYOUT_MODES.remove(deps.storage, info.sender.as_str()),
+        PayoutMode::Pull => PAYOUT_MODES.save(deps.storage, info.sender.as_str(), &mode)?
+    }
+    Ok(Response::new()
+        .add_attribute("action", "set_payout_mode")
+        .add_attribute("address", info.sender)
+        .add_attribute("mode", format!("{:?}", mode).to_lowercase())
+    )
+}
+
+pub fn withdraw(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    assets: Option<Vec<AssetInfo>>
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    assert_not_paused(deps.storage, Operation::Cancelling, &[])?;
+    _withdraw(deps, env, info, assets)
+}
+
+/**
+ * @dev Transfer out the sender's claimable balances, all of them unless `assets`
+ *  narrows them down. Balances the sender has none of are skipped.
+ */
+fn _withdraw(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    assets: Option<Vec<AssetInfo>>
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    // while winding down, short assets stay credited until they're covered again
+    let short = if CONFIG.load(deps.storage)?.withdrawal_mode {
+        short_assets(deps.as_ref(), &env)?
+    } else {
+        vec![]
+    };
+    let balances = CLAIMABLE.prefix(info.sender.as_str())
+        .range(deps.storage, None, None, CwOrder::Ascending)
+        .map(|item| item.map(|(_, balance)| balance))
+        .collect::<StdResult<Vec<Asset>>>()?;
+    let mut messages: Vec<CosmosMsg<TerraMsgWrapper>> = vec![];
+    for balance in balances {
+        if matches!(&assets, Some(assets) if !assets.contains(&balance.info)) || short.contains(&balance.info) {
+            continue;
+        }
+        CLAIMABLE.remove(deps.storage, (info.sender.as_str(), balance.info.as_bytes()));
+        sub_from_total(deps.storage, &ESCROW, &balance)?;
+        messages.push(balance.into_msg(&deps.querier, info.sender.clone())?);
+    }
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attribute("action", "withdraw")
+        .add_attribute("address", info.sender)
+    )
+}
+
+fn query_accrued_fees(deps: Deps) -> StdResult<AccruedFeesResponse> {
+    let fees = ACCRUED_FEES.range(deps.storage, None, None, CwOrder::Ascending)
+        .map(|item| item.map(|(_, fee)| fee))
+        .collect::<StdResult<Vec<Asset>>>()?;
+    Ok(AccruedFeesResponse { fees })
+}
+
+fn query_claimable(deps: Deps, address: String) -> StdResult<ClaimableResponse> {
+    let addr = deps.api.addr_validate(&address)?;
+    let balances = CLAIMABLE.prefix(addr.as_str())
+        .range(deps.storage, None, None, CwOrder::Ascending)
+        .map(|item| item.map(|(_, balance)| balance))
+        .collect::<StdResult<Vec<Asset>>>()?;
+    Ok(ClaimableResponse { address, mode: payout_mode(deps.storage, &addr)?, balances })
+}
+
+fn query_escrow_balances(deps: Deps, env: Env) -> StdResult<EscrowBalancesResponse> {
+    let mut balances: Vec<EscrowBalance> = vec![];
+    for (totals, is_fee) in [(&ESCROW, false), (&ACCRUED_FEES, true)] {
+        for item in totals.range(deps.storage, None, None, CwOrder::Ascending) {
+            let (_, total) = item?;
+            let balance = match balances.iter_mut().find(|b| b.info == total.info) {
+                Some(balance) => balance,
+                None => {
+                    balances.push(EscrowBalance {
+                        info: total.info.clone(),
+                        escrowed: Uint128::zero(),
+                        fees: Uint128::zero(),
+                        held: held_balance(deps, &env, &total.info)?
+                    });
+                    balances.last_mut().unwrap()
+                }
+            };
+            if is_fee {
+                balance.fees = total.amount;
+            } else {
+                balance.escrowed = total.amount;
+            }
+        }
+    }
+    let solvent = balances.iter().all(|b| matches!(b.escrowed.checked_add(b.fees), Ok(owed) if b.held >= owed));
+    Ok(EscrowBalancesResponse { balances, solvent })
+}
+
+/// What the contract actually holds of an asset
+fn held_balance(deps: Deps, env: &Env, info: &AssetInfo) -> StdResult<Uint128> {
+    match info {
+        AssetInfo::NativeToken { denom } => Ok(deps.querier.query_balance(&env.contract.address, denom)?.amount),
+        AssetInfo::Token { contract_addr } => {
+            let res: Cw20BalanceResponse = deps.querier.query_wasm_smart(contract_addr, &Cw20QueryMsg::Balance {
+                address: env.contract.address.to_string()
+            })?;
+            Ok(res.balance)
+        }
+    }
+}
+
+fn add_to_total(storage: &mut dyn Storage, totals: &Map<&[u8], Asset>, asset: &Asset) -> StdResult<()> {
+    let key = asset.info.as_bytes();
+    let mut total = totals.may_load(storage, key)?
+        .unwrap_or(Asset { info: asset.info.clone(), amount: Uint128::zero() });
+    total.amount = total.amount.checked_add(asset.amount)?;
+    totals.save(storage, key, &total)
+}
+
+fn sub_from_total(storage: &mut dyn Storage, totals: &Map<&[u8], Asset>, asset: &Asset) -> StdResult<()> {
+    let key = asset.info.as_bytes();
+    let mut total = totals.may_load(storage, key)?
+        .unwrap_or(Asset { info: asset.info.clone(), amount: Uint128::zero() });
+    total.amount = total.amount.checked_sub(asset.amount)?;
+    totals.save(storage, key, &total)
+}
+
+/// Save a new bid, its price joins the escrow
+fn save_bid(storage: &mut dyn Storage, bid: &Bid) -> StdResult<()> {
+    add_to_total(storage, &ESCROW, &bid.price)?;
+    bids().save(storage, (&bid.token_id, bid.nft_address.as_str()), bid)
+}
+
+/// Remove a bid, if any, and release its escrow
+fn remove_bid(storage: &mut dyn Storage, token_id: &str, nft_address: &str) -> StdResult<()> {
+    if let Some(bid) = bids().may_load(storage, (token_id, nft_address))? {
+        sub_from_total(storage, &ESCROW, &bid.price)?;
+        bids().remove(storage, (token_id, nft_address))?;
+    }
+    Ok(())
+}
+
+/// Remove a swap and release its sweetener from the escrow
+fn remove_swap(storage: &mut dyn Storage, swap: &Swap) -> StdResult<()> {
+    if let Some(sweetener) = &swap.sweetener {
+        sub_from_total(storage, &ESCROW, sweetener)?;
+    }
+    SWAPS.remove(storage, swap.swap_id.into());
+    Ok(())
+}
+
+fn payout_mode(storage: &dyn Storage, address: &Addr) -> StdResult<PayoutMode> {
+    Ok(PAYOUT_MODES.may_load(storage, address.as_str())?.unwrap_or_default())
+}
+
+/// Transfer `asset` minus the tax to a push-mode recipient, or credit all of it to a pull-mode one
+fn payout(
+    storage: &mut dyn Storage,
+    querier: &QuerierWrapper,
+    asset: Asset,
+    recipient: Addr
+) -> Result<Option<CosmosMsg<TerraMsgWrapper>>, ContractError> {
+    if payout_mode(storage, &recipient)? == PayoutMode::Pull {
+        credit(storage, &recipient, &asset)?;
+        return Ok(None);
+    }
+    Ok(Some(asset.into_msg(querier, recipient)?))
+}
+
+/// Add to the claimable balance of `recipient`, the funds stay in escrow until withdrawn
+fn credit(storage: &mut dyn Storage, recipient: &Addr, asset: &Asset) -> StdResult<()> {
+    if asset.amount.is_zero() {
+        return Ok(());
+    }
+    let key = (recipient.as_str(), asset.info.as_bytes());
+    let mut balance = CLAIMABLE.may_load(storage, key)?
+        .unwrap_or(Asset { info: asset.info.clone(), amount: Uint128::zero() });
+    balance.amount = balance.amount.checked_add(asset.amount)?;
+    CLAIMABLE.save(storage, key, &balance)?;
+    add_to_total(storage, &ESCROW, asset)
+}
+
+/// Recompute the escrow totals from the bids, swaps and claimable balances in storage
+fn rebuild_escrow(storage: &mut dyn Storage) -> StdResult<()> {
+    let keys = ESCROW.keys(storage, None, None, CwOrder::Ascending).collect::<Vec<_>>();
+    for key in keys {
+        ESCROW.remove(storage, &key);
+    }
+    let bid_prices = bids().range(storage, None, None, CwOrder::Ascending)
+        .map(|item| item.map(|(_, bid)| bid.price))
+        .collect::<StdResult<Vec<_>>>()?;
+    let sweeteners = SWAPS.range(storage, None, None, CwOrder::Ascending)
+        .filter_map(|item| item.map(|(_, swap)| swap.sweetener).transpose())
+        .collect::<StdResult<Vec<_>>>()?;
+    let claimable = CLAIMABLE.range(storage, None, None, CwOrder::Ascending)
+        .map(|item| item.map(|(_, balance)| balance))
+        .collect::<StdResult<Vec<_>>>()?;
+    for asset in bid_prices.iter().chain(sweeteners.iter()).chain(claimable.iter()) {
+        add_to_total(storage, &ESCROW, asset)?;
+    }
+    Ok(())
+}
+
+pub fn cleanup_expired(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    limit: Option<u32>
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    assert_not_paused(deps.storage, Operation::Cancelling, &[])?;
+    _cleanup_expired(deps, env, info, limit)
+}
+
+/**
+ * @dev Give back the escrow of expired orders and bids, soonest expiry first.
+ *  NFTs of expired orders go back to their sellers along with any bid on them,
+ *  the caller keeps the keeper fee of every expired bid refunded. Bids still
+ *  running on an expired order are refunded in full.
+ */
+fn _cleanup_expired(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    limit: Option<u32>
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    let con = CONFIG.load(deps.storage)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+
+    let mut expired_orders: Vec<Order> = vec![];
+    for kind in EXPIRING_KINDS {
+        let remaining = limit - expired_orders.len();
+        expired_orders.extend(orders().idx.expiry
+            .sub_prefix(kind.to_vec())
+            .range(deps.storage, None, None, CwOrder::Ascending)
+            .map(|item| item.map(|(_, order)| order))
+            .take_while(|item| item.as_ref().map_or(true, |order| order.expire_at.is_expired(&env.block)))
+            .take(remaining)
+            .collect::<StdResult<Vec<Order>>>()?);
+    }
+    let mut messages: Vec<CosmosMsg<TerraMsgWrapper>> = vec![];
+    let mut rewards: Vec<Asset> = vec![];
+    let mut bids_refunded = 0usize;
+    for order in expired_orders.iter() {
+        let key = (order.token_id.as_str(), order.nft_address.as_str());
+        if let Some(bid) = bids().may_load(deps.storage, key)? {
+            messages.extend(refund_expired_bid(deps.storage, &deps.querier, &con, &env.block, bid, &mut rewards)?);
+            bids_refunded += 1;
+        }
+        messages.extend(nft_transfer_messages(
+            &[BundleItem { token_id: order.token_id.clone(), nft_address: order.nft_address.clone() }],
+            &order.seller
+        )?);
+        remove_order(deps.storage, key.0, key.1)?;
+    }
+
+    let mut expired_bids: Vec<Bid> = vec![];
+    for kind in EXPIRING_KINDS {
+        let remaining = limit - expired_orders.len() - expired_bids.len();
+        expired_bids.extend(bids().idx.expiry
+            .sub_prefix(kind.to_vec())
+            .range(deps.storage, None, None, CwOrder::Ascending)
+            .map(|item| item.map(|(_, bid)| bid))
+            .take_while(|item| item.as_ref().map_or(true, |bid| bid.expire_at.is_expired(&env.block)))
+            .take(remaining)
+            .collect::<StdResult<Vec<Bid>>>()?);
+    }
+    for bid in expired_bids {
+        messages.extend(refund_expired_bid(deps.storage, &deps.querier, &con, &env.block, bid, &mut rewards)?);
+        bids_refunded += 1;
+    }
+
+    for reward in rewards {
+        messages.extend(payout(deps.storage, &deps.querier, reward, info.sender.clone())?);
+    }
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attribute("action", "cleanup_expired")
+        .add_attribute("orders", expired_orders.len().to_string())
+        .add_attribute("bids", bids_refunded.to_string())
+    )
+}
+
+/// Refund a bid, minus the keeper fee added to `rewards` if the bid itself expired
+fn refund_expired_bid(
+    storage: &mut dyn Storage,
+    querier: &QuerierWrapper,
+    con: &Config,
+    block: &BlockInfo,
+    bid: Bid,
+    rewards: &mut Vec<Asset>
+) -> Result<Option<CosmosMsg<TerraMsgWrapper>>, ContractError> {
+    let key = (bid.token_id.as_str(), bid.nft_address.as_str());
+    remove_bid(storage, key.0, key.1)?;
+    COUNTER_OFFERS.remove(storage, key);
+
+    let fee = if bid.expire_at.is_expired(block) {
+        bid.price.amount * con.keeper_fee
+    } else {
+        Uint128::zero()
+    };
+    if !fee.is_zero() {
+        match rewards.iter_mut().find(|r| r.info == bid.price.info) {
+            Some(reward) => reward.amount = reward.amount.checked_add(fee)?,
+            None => rewards.push(Asset { info: bid.price.info.clone(), amount: fee })
+        }
+    }
+    let refund = Asset {
+        info: bid.price.info,
+        amount: bid.price.amount.checked_sub(fee)?
+    };
+    payout(storage, querier, refund, bid.bidder)
+}
+
+pub fn batch_create_orders(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    orders: Vec<OrderMsg>,
+    mode: BatchMode
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    execute_batch(deps, "batch_create_orders", orders, mode,
+        |o| format!("{}/{}", o.nft_address, o.token_id),
+        |deps, o| create_order(deps, env.clone(), info.clone(), o.token_id, o.nft_address, o.price, o.expire_at, o.payout_swap, o.reserved_for)
+    )
+}
+
+pub fn batch_update_orders(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    orders: Vec<OrderMsg>,
+    mode: BatchMode
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    execute_batch(deps, "batch_update_orders", orders, mode,
+        |o| format!("{}/{}", o.nft_address, o.token_id),
+        |deps, o| update_order(deps, env.clone(), info.clone(), o.token_id, o.nft_address, o.price, o.expire_at, o.payout_swap, o.reserved_for)
+    )
+}
+
+pub fn batch_cancel_orders(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    orders: Vec<NftItemMsg>,
+    mode: BatchMode
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    execute_batch(deps, "batch_cancel_orders", orders, mode,
+        |o| format!("{}/{}", o.nft_address, o.token_id),
+        |deps, o| cancel_order(deps, env.clone(), info.clone(), o.token_id, o.nft_address)
+    )
+}
+
+pub fn batch_buy(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    orders: Vec<BuyMsg>
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    let nft_addresses: Vec<&str> = orders.iter().map(|o| o.nft_address.as_str()).collect();
+    assert_not_paused(deps.storage, Operation::Buying, &nft_addresses)?;
+    _batch_buy(deps, env, info, orders)
+}
+
+pub fn sweep_floor(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    nft_address: String,
+    max_count: u32,
+    max_total_price: Asset
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    assert_not_paused(deps.storage, Operation::Buying, &[&nft_address])?;
+    _sweep_floor(deps, env, info, nft_address, max_count, max_total_price)
+}
+
+/**
+ * @dev Buy several listings, possibly across collections and denoms,
+ *  paid by the native funds sent along. Anything not spent is refunded.
+ */
+fn _batch_buy(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    items: Vec<BuyMsg>
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    let con = CONFIG.load(deps.storage)?;
+    let mut spent: BTreeMap<String, Uint128> = BTreeMap::new();
+    let mut selected: Vec<(Order, Settlement)> = vec![];
+    for item in items {
+        let order = orders().may_load(deps.storage, (&item.token_id, &item.nft_address))?
+            .ok_or(ContractError::NoOrder {})?;
+        if selected.iter().any(|(o, _)| o.token_id == order.token_id && o.nft_address == order.nft_address) {
+            return Err(ContractError::NoOrder {});
+        }
+        if order.price != item.price {
+            return Err(ContractError::InvalidPrice {});
+        }
+        if order.seller == info.sender {
+            return Err(ContractError::Unauthorized {});
+        }
+        assert_not_reserved(&order, &info.sender)?;
+        if order.expire_at.is_expired(&env.block) {
+            return Err(ContractError::Expired {});
+        }
+        let settlement = compute_settlement(&deps.querier, fee_rate(deps.storage, &con, &[&order.nft_address], &order.seller, &order.price.info)?, &order.price, &order.seller,
+            collection_royalties(deps.storage, &[&order.nft_address], &order.price)?, order.payout_swap.as_ref(), con.buyer_pays_tax)?;
+        add_spent(&mut spent, &order.price.info, settlement.breakdown.buyer_total)?;
+        selected.push((order, settlement));
+    }
+    let bought = selected.len();
+    let messages = execute_sales(deps, &env.block, &info, selected, &spent)?;
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attribute("action", "batch_buy")
+        .add_attribute("buyer", info.sender)
+        .add_attribute("count", bought.to_string())
+    )
+}
+
+/// Listings a floor sweep looks at before giving up on filling `max_count`
+const MAX_SWEEP_SCAN: usize = 100;
+
+/**
+ * @dev Buy the cheapest active listings of a collection priced in the asset of
+ *  `max_total_price`, until `max_count` items are bought or the next one would
+ *  exceed the budget. Listings that can't be bought are skipped, up to
+ *  `MAX_SWEEP_SCAN` examined in all. Anything not spent is refunded.
+ */
+fn _sweep_floor(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    nft_address: String,
+    max_count: u32,
+    max_total_price: Asset
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    let con = CONFIG.load(deps.storage)?;
+    let nft_address = deps.api.addr_validate(&nft_address)?;
+
+    let mut selected: Vec<(Order, Settlement)> = vec![];
+    let mut total = Uint128::zero();
+    let floor = orders().idx.price
+        .sub_prefix((nft_address.as_bytes().to_vec(), max_total_price.info.as_bytes().to_vec()))
+        .range(deps.storage, None, None, CwOrder::Ascending)
+        .take(MAX_SWEEP_SCAN);
+    for item in floor {
+        if selected.len() >= max_count as usize {
+            break;
+        }
+        let (_, order) = item?;
+        if order.expire_at.is_expired(&env.block) || order.seller == info.sender || assert_not_reserved(&order, &info.sender).is_err()
+            || assert_token_not_blocked(deps.storage, &order.token_id, order.nft_address.as_str()).is_err()
+            || assert_not_blocked(deps.storage, &[&order.seller]).is_err() {
+            continue;
+        }
+        let settlement = compute_settlement(&deps.querier, fee_rate(deps.storage, &con, &[&order.nft_address], &order.seller, &order.price.info)?, &order.price, &order.seller,
+            collection_royalties(deps.storage, &[&order.nft_address], &order.price)?, order.payout_swap.as_ref(), con.buyer_pays_tax)?;
+        let next_total = total.checked_add(settlement.breakdown.buyer_total)?;
+        if next_total > max_total_price.amount {
+            break;
+        }
+        total = next_total;
+        selected.push((order, settlement));
+    }
+
+    let mut spent: BTreeMap<String, Uint128> = BTreeMap::new();
+    add_spent(&mut spent, &max_total_price.info, total)?;
+    let bought = selected.len();
+    let messages = execute_sales(deps, &env.block, &info, selected, &spent)?;
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attribute("action", "sweep_floor")
+        .add_attribute("nft_address", nft_address)
+        .add_attribute("buyer", info.sender)
+        .add_attribute("count", bought.to_string())
+        .add_attribute("total", total)
+    )
+}
+
+/**
+ * @dev Settle several sales paid at once by the funds sent along,
+ *  which must cover `spent` per denom. The rest is refunded to the buyer.
+ */
+fn execute_sales(
+    mut deps: DepsMut,
+    block: &BlockInfo,
+    info: &MessageInfo,
+    sales: Vec<(Order, Settlement)>,
+    spent: &BTreeMap<String, Uint128>
+) -> Result<Vec<CosmosMsg<TerraMsgWrapper>>, ContractError> {
+    let refunds = refund_unspent(&deps.querier, info, spent)?;
+    let mut messages: Vec<CosmosMsg<TerraMsgWrapper>> = vec![];
+    for (order, settlement) in sales {
+        messages.extend(execute_sale(deps.branch(), &order, settlement, &info.sender, block)?);
+    }
+    messages.extend(refunds);
+    Ok(messages)
+}
+
+fn add_spent(spent: &mut BTreeMap<String, Uint128>, info: &AssetInfo, amount: Uint128) -> Result<(), ContractError> {
+    match info {
+        AssetInfo::NativeToken { denom } => {
+            let entry = spent.entry(denom.clone()).or_default();
+            *entry = entry.checked_add(amount)?;
+            Ok(())
+        }
+        // cw20 tokens can't be attached to a combined payment
+        AssetInfo::Token { .. } => Err(ContractError::AssetNotAccepted { asset: info.to_string() })
+    }
+}
+
+/// Check the sent funds cover what was spent per denom and refund the rest
+fn refund_unspent(
+    querier: &QuerierWrapper,
+    info: &MessageInfo,
+    spent: &BTreeMap<String, Uint128>
+) -> Result<Vec<CosmosMsg<TerraMsgWrapper>>, ContractError> {
+    for (denom, amount) in spent.iter() {
+        let sent = info.funds.iter().find(|c| c.denom == *denom).map(|c| c.amount).unwrap_or_default();
+        if sent < *amount {
+            return Err(ContractError::InsufficientFunds { denom: denom.clone(), required: *amount, sent });
+        }
+    }
+    let mut messages: Vec<CosmosMsg<TerraMsgWrapper>> = vec![];
+    for coin in info.funds.iter() {
+        let refund = coin.amount.checked_sub(spent.get(&coin.denom).copied().unwrap_or_default())?;
+        if !refund.is_zero() {
+            let asset = Asset {
+                info: AssetInfo::NativeToken { denom: coin.denom.clone() },
+                amount: refund
+            };
+            messages.push(asset.into_msg(querier, info.sender.clone())?);
+        }
+    }
+    Ok(messages)
+}
+
+/**
+ * @dev Run `op` for every item and merge the resulting messages.
+ *  In all-or-nothing mode the first failure aborts the whole batch,
+ *  in best-effort mode failures are skipped. Every item is reported as an
+ *  `item` attribute valued `<label>:ok` or `<label>:failed:<reason>`.
+ *  Every single-item handler validates before writing to storage,
+ *  so a failed item leaves no partial state behind.
+ */
+fn execute_batch<T, L, F>(
+    mut deps: DepsMut,
+    action: &str,
+    items: Vec<T>,
+    mode: BatchMode,
+    label: L,
+    mut op: F
+) -> Result<Response<TerraMsgWrapper>, ContractError>
+where
+    L: Fn(&T) -> String,
+    F: FnMut(DepsMut, T) -> Result<Response<TerraMsgWrapper>, ContractError>
+{
+    let mut response = Response::new().add_attribute("action", action);
+    let mut succeeded = 0u32;
+    let mut failed = 0u32;
+    for item in items {
+        let item_label = label(&item);
+        match op(deps.branch(), item) {
+            Ok(res) => {
+                succeeded += 1;
+                response = response
+                    .add_submessages(res.messages)
+                    .add_attribute("item", format!("{}:ok", item_label));
+            }
+            Err(err) => match mode {
+                BatchMode::AllOrNothing => return Err(err),
+                BatchMode::BestEffort => {
+                    failed += 1;
+                    response = response.add_attribute("item", format!("{}:failed:{}", item_label, err));
+                }
+            }
+        }
+    }
+    Ok(response
+        .add_attribute("succeeded", succeeded.to_string())
+        .add_attribute("failed", failed.to_string())
+    )
+}
+
+/// `tax` is what the contract pays on top of the transferred amount
+enum Payout {
+    Transfer { recipient: Addr, asset: Asset, tax: Uint128 },
+    /// native proceeds swapped on the Terra market on the way to the recipient
+    Swap { recipient: Addr, offer_coin: Coin, ask_denom: String, tax: Uint128 }
+}
+
+struct Settlement {
+    breakdown: SettlementResponse,
+    /// amounts actually transferred, tax already accounted for
+    payouts: Vec<Payout>
+}
+
+impl Settlement {
+    /// Accrues the marketplace fee and returns the transfers for push-mode recipients.
+    /// Pull-mode recipients are credited the amount plus the tax it would have cost.
+    /// A payout swap can't be credited, so it fails the sale until the seller
+    /// switches back to push mode or drops the swap.
+    fn into_messages(self, storage: &mut dyn Storage) -> Result<Vec<CosmosMsg<TerraMsgWrapper>>, ContractError> {
+        if !self.breakdown.marketplace_fee.is_zero() {
+            add_to_total(storage, &ACCRUED_FEES, &Asset {
+                info: self.breakdown.price.info.clone(),
+                amount: self.breakdown.marketplace_fee
+            })?;
+        }
+        let mut messages: Vec<CosmosMsg<TerraMsgWrapper>> = vec![];
+        for payout in self.payouts {
+            let (recipient, tax) = match &payout {
+                Payout::Transfer { recipient, tax, .. } | Payout::Swap { recipient, tax, .. } => (recipient.clone(), *tax)
+            };
+            if payout_mode(storage, &recipient)? == PayoutMode::Pull {
+                let mut asset = match payout {
+                    Payout::Transfer { asset, .. } => asset,
+                    Payout::Swap { .. } => return Err(ContractError::PayoutSwapInPullMode {})
+                };
+                asset.amount = asset.amount.checked_add(tax)?;
+                credit(storage, &recipient, &asset)?;
+                continue;
+            }
+            messages.push(match payout {
+                Payout::Transfer { recipient, asset, .. } => asset.into_send_msg(recipient)?,
+                Payout::Swap { recipient, offer_coin, ask_denom, .. } =>
+                    create_swap_send_msg(recipient.to_string(), offer_coin, ask_denom)
+            });
+        }
+        Ok(messages)
+    }
+}
+
+/**
+ * @dev Split a sale price into marketplace fee, royalty and seller proceeds.
+ *  The fee stays in the contract until claimed, so it isn't taxed. The Terra
+ *  tax is computed once per payout: when the buyer bears it, it is added on
+ *  top of the price, otherwise it is deducted from each payout.
+ *  Seller proceeds are swapped to the listing's payout denom when one is set.
+ *  Shared by settlement and the simulation queries.
+ */
+fn compute_settlement(
+    querier: &QuerierWrapper,
+    fee_rate: Decimal,
+    price: &Asset,
+    seller: &Addr,
+    royalties: Vec<(Addr, Uint128)>,
+    payout_swap: Option<&PayoutSwap>,
+    buyer_pays_tax: bool
+) -> Result<Settlement, ContractError> {
+    let marketplace_fee = price.amount * fee_rate;
+    let royalty = royalties.iter().try_fold(Uint128::zero(), |total, (_, amount)| total.checked_add(*amount))?;
+    let seller_amount = price.amount.checked_sub(marketplace_fee)?.checked_sub(royalty)?;
+
+    // (tax, net amount leaving the contract) of a payout
+    let taxed = |amount: Uint128| -> Result<(Uint128, Uint128), ContractError> {
+        let gross = Asset { info: price.info.clone(), amount };
+        if buyer_pays_tax {
+            Ok((gross.compute_tax_on_top(querier)?, amount))
+        } else {
+            let payout_tax = gross.compute_tax(querier)?;
+            Ok((payout_tax, amount.checked_sub(payout_tax)?))
+        }
+    };
+
+    let mut tax = Uint128::zero();
+    let mut payouts: Vec<Payout> = vec![];
+    for (creator, amount) in royalties {
+        let (royalty_tax, royalty_net) = taxed(amount)?;
+        tax = tax.checked_add(royalty_tax)?;
+        payouts.push(Payout::Transfer {
+            recipient: creator,
+            asset: Asset { info: price.info.clone(), amount: royalty_net },
+            tax: royalty_tax
+        });
+    }
+
+    let (seller_tax, seller_net) = taxed(seller_amount)?;
+    tax = tax.checked_add(seller_tax)?;
+    let mut seller_payout: Option<Coin> = None;
+    if !seller_net.is_zero() {
+        match (payout_swap, &price.info) {
+            (Some(swap), AssetInfo::NativeToken { denom }) => {
+                let offer_coin = Coin { denom: denom.clone(), amount: seller_net };
+                let receive = TerraQuerier::new(querier)
+                    .query_swap(offer_coin.clone(), swap.denom.clone())?
+                    .receive;
+                if receive.amount < swap.min_received {
+                    return Err(ContractError::PayoutBelowMinimum {
+                        min_received: swap.min_received,
+                        received: receive.amount
+                    });
+                }
+                seller_payout = Some(receive);
+                payouts.push(Payout::Swap {
+                    recipient: seller.clone(),
+                    offer_coin,
+                    ask_denom: swap.denom.clone(),
+                    tax: seller_tax
+                });
+            }
+            (Some(_), AssetInfo::Token { .. }) => return Err(ContractError::InvalidPayoutSwap {}),
+            (None, _) => payouts.push(Payout::Transfer {
+                recipient: seller.clone(),
+                asset: Asset { info: price.info.clone(), amount: seller_net },
+                tax: seller_tax
+            })
+        }
+    }
+    let buyer_total = if buyer_pays_tax { price.amount.checked_add(tax)? } else { price.amount };
+    Ok(Settlement {
+        breakdown: SettlementResponse {
+            price: price.clone(),
+            fee_rate,
+            marketplace_fee,
+            royalty,
+            tax,
+            seller_net,
+            seller_payout,
+            buyer_total
+        },
+        payouts
+    })
+}
+
+/// A payout swap needs a native payment asset, a different native payout denom
+/// and a seller paid out in push mode, credits aren't swapped
+fn assert_valid_payout_swap(storage: &dyn Storage, seller: &Addr, price: &Asset, payout_swap: &Option<PayoutSwap>) -> Result<(), ContractError> {
+    if let Some(swap) = payout_swap {
+        if payout_mode(storage, seller)? == PayoutMode::Pull {
+            return Err(ContractError::PayoutSwapInPullMode {});
+        }
+        match &price.info {
+            AssetInfo::NativeToken { denom } if !swap.denom.is_empty() && *denom != swap.denom => Ok(()),
+            _ => Err(ContractError::InvalidPayoutSwap {})
+        }
+    } else {
+        Ok(())
+    }
+}
+
+/// The owner or one of the curators
+fn assert_curator(storage: &dyn Storage, sender: &Addr) -> Result<Config, ContractError> {
+    let con = CONFIG.load(storage)?;
+    if con.owner != *sender && !con.curators.contains(sender) {
+        return Err(ContractError::Unauthorized {});
+    }
+    Ok(con)
+}
+
+/// The owner or one of the moderators
+fn assert_moderator(storage: &dyn Storage, sender: &Addr) -> Result<Config, ContractError> {
+    let con = CONFIG.load(storage)?;
+    if con.owner != *sender && !con.moderators.contains(sender) {
+        return Err(ContractError::Unauthorized {});
+    }
+    Ok(con)
+}
+
+fn assert_token_not_blocked(storage: &dyn Storage, token_id: &str, nft_address: &str) -> Result<(), ContractError> {
+    if BLOCKED_TOKENS.has(storage, (token_id, nft_address)) {
+        return Err(ContractError::TokenBlocked {});
+    }
+    Ok(())
+}
+
+fn assert_not_blocked(storage: &dyn Storage, addresses: &[&Addr]) -> Result<(), ContractError> {
+    match addresses.iter().find(|address| BLOCKED_ADDRESSES.has(storage, address.as_str())) {
+        Some(address) => Err(ContractError::AddressBlocked { address: address.to_string() }),
+        None => Ok(())
+    }
+}
+
+/// Banned collections can't be listed, unverified ones neither while verification is required
+fn assert_listable(storage: &dyn Storage, con: &Config, nft_address: &str) -> Result<(), ContractError> {
+    match COLLECTIONS.may_load(storage, nft_address)?.map(|c| c.status) {
+        Some(CollectionStatus::Banned) => Err(ContractError::CollectionBanned {}),
+        Some(CollectionStatus::Verified) => Ok(()),
+        _ if con.require_verified => Err(ContractError::CollectionNotVerified {}),
+        _ => Ok(())
+    }
+}
+
+/// Royalty owed to each creator for a sale of NFTs from `nft_addresses`, the price split evenly among them
+fn collection_royalties(storage: &dyn Storage, nft_addresses: &[&Addr], price: &Asset) -> StdResult<Vec<(Addr, Uint128)>> {
+    let mut royalties: Vec<(Addr, Uint128)> = vec![];
+    for nft_address in nft_addresses {
+        let collection = match COLLECTIONS.may_load(storage, nft_address.as_str())? {
+            Some(collection) => collection,
+            None => continue
+        };
+        let amount = price.amount.multiply_ratio(1u128, nft_addresses.len() as u128) * collection.royalty_rate;
+        if amount.is_zero() {
+            continue;
+        }
+        match royalties.iter_mut().find(|(creator, _)| *creator == collection.creator) {
+            Some((_, total)) => *total = total.checked_add(amount)?,
+            None => royalties.push((collection.creator, amount))
+        }
+    }
+    Ok(royalties)
+}
+
+/**
+ * @dev Marketplace fee rate of a sale: the collection override or the default rate,
+ *  lowered by the best tier the seller's sold volume in the price asset reaches.
+ *  A bundle pays the highest rate among its collections. Never above `owner_cut_rate_max`.
+ */
+fn fee_rate(storage: &dyn Storage, con: &Config, nft_addresses: &[&Addr], seller: &Addr, price_info: &AssetInfo) -> StdResult<Decimal> {
+    let mut rate: Option<Decimal> = None;
+    for nft_address in nft_addresses {
+        let collection_rate = COLLECTION_FEES.may_load(storage, nft_address.as_str())?.unwrap_or(con.owner_cut_rate);
+        rate = Some(rate.map_or(collection_rate, |rate| rate.max(collection_rate)));
+    }
+    let mut rate = rate.unwrap_or(con.owner_cut_rate);
+    let sold = user_volumes().may_load(storage, (seller.as_bytes(), price_info.as_bytes()))?
+        .map(|volume| volume.sold)
+        .unwrap_or_default();
+    let tier_rate = con.fee_tiers.iter()
+        .filter(|tier| tier.asset_info == *price_info && sold >= tier.min_volume)
+        .map(|tier| tier.rate)
+        .min();
+    if let Some(tier_rate) = tier_rate {
+        rate = rate.min(tier_rate);
+    }
+    Ok(rate.min(con.owner_cut_rate_max))
+}
+
+fn bundle_fee_rate(storage: &dyn Storage, con: &Config, bundle: &Bundle) -> StdResult<Decimal> {
+    let nft_addresses: Vec<&Addr> = bundle.nfts.iter().map(|item| &item.nft_address).collect();
+    fee_rate(storage, con, &nft_addresses, &bundle.seller, &bundle.price.info)
+}
+
+fn bundle_royalties(storage: &dyn Storage, bundle: &Bundle) -> StdResult<Vec<(Addr, Uint128)>> {
+    let nft_addresses: Vec<&Addr> = bundle.nfts.iter().map(|item| &item.nft_address).collect();
+    collection_royalties(storage, &nft_addresses, &bundle.price)
+}
+
+fn assert_owner(storage: &dyn Storage, sender: &Addr) -> Result<Config, ContractError> {
+    let con = CONFIG.load(storage)?;
+    if con.owner != *sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    Ok(con)
+}
+
+/// Only native coins can be paid in, there is no cw20 receive hook to collect tokens
+fn validate_asset_infos(assets: Vec<AssetInfo>) -> Result<Vec<AssetInfo>, ContractError> {
+    if let Some(token) = assets.iter().find(|asset| !asset.is_native_token()) {
+        return Err(ContractError::UnsupportedAsset { asset: token.to_string() });
+    }
+    Ok(assets)
+}
+
+fn join_asset_infos(assets: &[AssetInfo]) -> String {
+    assets.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(",")
+}
+
+/// Accepted payment assets of a collection, its override or else the global list
+fn accepted_assets(storage: &dyn Storage, nft_address: &str) -> StdResult<Vec<AssetInfo>> {
+    match COLLECTION_ACCEPTED_ASSETS.may_load(storage, nft_address)? {
+        Some(assets) => Ok(assets),
+        None => ACCEPTED_ASSETS.load(storage)
+    }
+}
+
+fn assert_accepted_asset(storage: &dyn Storage, nft_address: &str, asset: &AssetInfo) -> Result<(), ContractError> {
+    if !accepted_assets(storage, nft_address)?.iter().any(|a| a.equal(asset)) {
+        return Err(ContractError::AssetNotAccepted { asset: asset.to_string() });
+    }
+    Ok(())
+}
+
+fn query_nft_owner(
+    querier: &QuerierWrapper,
+    nft_address: &str,
+    token_id: &str
+) -> Result<String, ContractError> {
+    let owner_res: OwnerOfResponse = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: nft_address.to_string(),
+        msg: to_binary(&Cw721QueryMsg::OwnerOf { token_id: token_id.to_string(), include_expired: Some(false) })?,
+    })).map_err(|e| ContractError::Cw721QueryFailed {
+        nft_address: nft_address.to_string(),
+        token_id: token_id.to_string(),
+        reason: e.to_string()
+    })?;
+    Ok(owner_res.owner)
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(deps: DepsMut, _env: Env, msg: MigrateMsg) -> Result<Response, ContractError> {
+    // the single accepted token of the config became a whitelist of payment assets
+    match msg.accepted_assets {
+        Some(assets) => ACCEPTED_ASSETS.save(deps.storage, &validate_asset_infos(assets)?)?,
+        None => if ACCEPTED_ASSETS.may_load(deps.storage)?.is_none() {
+            return Err(ContractError::MissingAcceptedAssets {});
+        }
+    }
+    // the single pause flag became a bitmap of operations
+    let legacy_pause = LEGACY_PAUSE.load(deps.storage)?;
+    CONFIG.update(deps.storage, |mut c| -> StdResult<_> {
+        if legacy_pause.paused {
+            c.paused_operations = Operation::to_bits(&Operation::ALL);
+        }
+        Ok(c)
+    })?;
+    // deployments from before contract versions were recorded
+    let legacy = get_contract_version(deps.storage).is_err();
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+    if !legacy {
+        reindex_orders(deps.storage)?;
+        rebuild_escrow(deps.storage)?;
+        return Ok(Response::new().add_attribute("action", "migrate"));
+    }
+
+    // orders and bids used to expire at raw unix seconds, rewrite them with
+    // `Expiration::AtTime` and rebuild their indexes from scratch
+    let legacy_orders = LEGACY_ORDERS.range(deps.storage, None, None, CwOrder::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    let legacy_bids = LEGACY_BIDS.range(deps.storage, None, None, CwOrder::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    for namespace in ["orders__price", "orders__expiry", "orders__expiration", "bids__expiry", "bids__expiration"] {
+        clear_namespace(deps.storage, namespace.as_bytes());
+    }
+    for (pk, _) in legacy_orders.iter() {
+        LEGACY_ORDERS.remove(deps.storage, pk);
+    }
+    for (pk, _) in legacy_bids.iter() {
+        LEGACY_BIDS.remove(deps.storage, pk);
+    }
+    for (_, order) in legacy_orders.iter() {
+        let order: Order = order.clone().into();
+        save_order(deps.storage, &order)?;
+    }
+    for (_, bid) in legacy_bids.iter() {
+        let bid: Bid = bid.clone().into();
+        bids().save(deps.storage, (&bid.token_id, bid.nft_address.as_str()), &bid)?;
+    }
+    rebuild_escrow(deps.storage)?;
+    Ok(Response::new()
+        .add_attribute("action", "migrate")
+        .add_attribute("orders", legacy_orders.len().to_string())
+        .add_attribute("bids", legacy_bids.len().to_string())
+    )
+}
+
+/// Re-save every order, which fills in indexes added since it was last written
+fn reindex_orders(storage: &mut dyn Storage) -> StdResult<()> {
+    let listed = orders().range(storage, None, None, CwOrder::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    for (_, order) in listed {
+        orders().save(storage, (&order.token_id, order.nft_address.as_str()), &order)?;
+    }
+    Ok(())
+}
+
+/// Remove every key stored under a storage-plus namespace
+fn clear_namespace(storage: &mut dyn Storage, namespace: &[u8]) {
+    let mut start = (namespace.len() as u16).to_be_bytes().to_vec();
+    start.extend_from_slice(namespace);
+    let mut end = start.clone();
+    if let Some(last) = end.last_mut() {
+        *last += 1;
+    }
+    let keys: Vec<Vec<u8>> = storage.range(Some(&start), Some(&end), CwOrder::Ascending)
+        .map(|(key, _)| key)
+        .collect();
+    for key in keys {
+        storage.remove(&key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::{mock_env, mock_info};
+    use crate::mock_querier::mock_dependencies;
+    use crate::msg::{ InstantiateMsg };
+    use crate::asset::{ Asset, AssetInfo };
+    use crate::state::{ LegacyOrder, LegacyBid };
+    use cosmwasm_std::{ coins, from_binary, BankMsg, Coin, Timestamp };
+
+    const NFT: &str = "terra1rmw87h769rt553myzcvnqavvnqzqxm2r9twsju";
+
+    fn native(denom: &str) -> AssetInfo {
+        AssetInfo::NativeToken { denom: denom.to_string() }
+    }
+
+    fn at(seconds: u64) -> Expiration {
+        Expiration::AtTime(Timestamp::from_seconds(seconds))
+    }
+
+    fn uluna(amount: u128) -> Asset {
+        Asset {
+            amount: Uint128::from(amount),
+            info: AssetInfo::NativeToken { denom: "uluna".to_string() }
+        }
+    }
+
+    fn do_instantiate(deps: DepsMut) {
+        let instantiate_msg = InstantiateMsg {
+            decimals: Uint128::from(6u128),
+            name: "nftmx".to_string(),
+            symbol: "NFTMX".to_string(),
+            accepted_assets: vec![native("uluna"), native("uusd")],
+            owner_cut_rate: Decimal::percent(2),
+            buyer_pays_tax: false
+        };
+        instantiate(deps, mock_env(), mock_info("owner", &[]), instantiate_msg).unwrap();
+    }
+
+    fn list(deps: DepsMut, seller: &str, token_id: &str, price: Asset) {
+        let expire_at = mock_env().block.time.seconds() + 3600;
+        _create_order(deps, mock_env(), mock_info(seller, &[]), token_id.to_string(), NFT.to_string(), price, at(expire_at), None, None).unwrap();
+    }
+
+    mod instantiate {
+        use super::*;
+
+        #[test]
+        fn works() {
+            let mut deps = mock_dependencies(&[]);
+            let instantiate_msg = InstantiateMsg {
+                decimals: Uint128::from(11223344u128),
+                name: "testing2".to_string(),
+                symbol: "testing".to_string(),
+                accepted_assets: vec![native("uluna"), native("uusd")],
+                owner_cut_rate: Decimal::from_ratio(Uint128::from(10u64), Uint128::from(100u64)),
+                buyer_pays_tax: false
+            };
+            let res = instantiate(deps.as_mut(), mock_env(), mock_info("signer", &[]), instantiate_msg).unwrap();
+            assert_eq!(0, res.messages.len());
+
+        }
+    }
+
+    #[test]
+    fn create_order_works() {
+        let mut deps = mock_dependencies(&[]);
+        do_instantiate(deps.as_mut());
+        let info = mock_info("signer", &[]);
+        let env = mock_env();
+        let expiration = 1648958996u64;
+        let expired_expiration = env.block.time.seconds();
+
+        let price = Asset {
+            amount: Uint128::from(10000u128),
+            info: AssetInfo::NativeToken {denom : "uluna".to_string()}
+        };
+        let zeroprice = Asset {
             amount: Uint128::from(0u64),
             info: AssetInfo::NativeToken {denom : "uluna".to_string()}
         };
 
-        let nft_address = "terra1rmw87h769rt553myzcvnqavvnqzqxm2r9twsju".to_string();
-        let token_id = "2".to_string();
+        let nft_address = "terra1rmw87h769rt553myzcvnqavvnqzqxm2r9twsju".to_string();
+        let token_id = "2".to_string();
+
+        let zero_price_err = _create_order(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            token_id.clone(),
+            nft_address.clone(),
+            zeroprice.clone(),
+            at(expiration),
+            None,
+            None
+        ).unwrap_err();
+        assert_eq!(zero_price_err, ContractError::InvalidPrice {});
+
+        let expired_err = _create_order(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            token_id.clone(),
+            nft_address.clone(),
+            price.clone(),
+            at(expired_expiration),
+            None,
+            None
+        ).unwrap_err();
+        assert_eq!(expired_err, ContractError::InvalidExpiration {});
+
+        let res = _create_order(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            token_id.clone(),
+            nft_address.clone(),
+            price.clone(),
+            at(expiration),
+            None,
+            None
+        ).unwrap();
+        assert_eq!(res, Response::new()
+            .add_attribute("action", "create_order")
+            .add_attribute("token_id", token_id)
+            .add_attribute("nft_address", nft_address)
+            .add_attribute("seller", info.sender)
+            .add_attribute("expire_at", at(expiration).to_string())
+            .add_attribute("price", price.amount)
+        );
+    }
+
+    #[test]
+    fn create_order_owner_errors() {
+        let mut deps = mock_dependencies(&[]);
+        do_instantiate(deps.as_mut());
+        let expire_at = mock_env().block.time.seconds() + 3600;
+
+        let err = create_order(deps.as_mut(), mock_env(), mock_info("seller", &[]), "1".to_string(), NFT.to_string(), uluna(100), at(expire_at), None, None).unwrap_err();
+        assert!(matches!(err, ContractError::Cw721QueryFailed { .. }));
+
+        deps.querier.with_nft_owner(NFT, "1", "someone_else");
+        let err = create_order(deps.as_mut(), mock_env(), mock_info("seller", &[]), "1".to_string(), NFT.to_string(), uluna(100), at(expire_at), None, None).unwrap_err();
+        assert_eq!(err, ContractError::NoOwner {});
+
+        deps.querier.with_nft_owner(NFT, "1", "seller");
+        create_order(deps.as_mut(), mock_env(), mock_info("seller", &[]), "1".to_string(), NFT.to_string(), uluna(100), at(expire_at), None, None).unwrap();
+
+        set_paused(deps.as_mut(), mock_env(), mock_info("owner", &[]), vec![Operation::Listing]).unwrap();
+        let err = create_order(deps.as_mut(), mock_env(), mock_info("seller", &[]), "1".to_string(), NFT.to_string(), uluna(100), at(expire_at), None, None).unwrap_err();
+        assert_eq!(err, ContractError::MarketplacePaused {});
+    }
+
+    #[test]
+    fn missing_order_and_bid_errors() {
+        let mut deps = mock_dependencies(&[]);
+        do_instantiate(deps.as_mut());
+        let token_id = "1".to_string();
+        let expire_at = mock_env().block.time.seconds() + 3600;
+
+        let err = update_order(deps.as_mut(), mock_env(), mock_info("seller", &[]), token_id.clone(), NFT.to_string(), uluna(100), at(expire_at), None, None).unwrap_err();
+        assert_eq!(err, ContractError::NoOrder {});
+        let err = cancel_order(deps.as_mut(), mock_env(), mock_info("seller", &[]), token_id.clone(), NFT.to_string()).unwrap_err();
+        assert_eq!(err, ContractError::NoOrder {});
+        let err = safe_execute_order(deps.as_mut(), mock_env(), mock_info("buyer", &[]), token_id.clone(), NFT.to_string(), uluna(100)).unwrap_err();
+        assert_eq!(err, ContractError::NoOrder {});
+        let err = create_bid(deps.as_mut(), mock_env(), mock_info("bidder", &[]), token_id.clone(), NFT.to_string(), uluna(100), at(expire_at)).unwrap_err();
+        assert_eq!(err, ContractError::NoOrder {});
+        let err = accept_bid(deps.as_mut(), mock_env(), mock_info("seller", &[]), token_id.clone(), NFT.to_string(), uluna(100)).unwrap_err();
+        assert_eq!(err, ContractError::NoOrder {});
+        let err = cancel_bid(deps.as_mut(), mock_env(), mock_info("bidder", &[]), token_id.clone(), NFT.to_string()).unwrap_err();
+        assert_eq!(err, ContractError::NoBid {});
+
+        list(deps.as_mut(), "seller", &token_id, uluna(100));
+        let err = accept_bid(deps.as_mut(), mock_env(), mock_info("seller", &[]), token_id.clone(), NFT.to_string(), uluna(100)).unwrap_err();
+        assert_eq!(err, ContractError::NoBid {});
+    }
+
+    #[test]
+    fn order_permission_and_price_errors() {
+        let mut deps = mock_dependencies(&[]);
+        do_instantiate(deps.as_mut());
+        let token_id = "1".to_string();
+        let expire_at = mock_env().block.time.seconds() + 3600;
+        list(deps.as_mut(), "seller", &token_id, uluna(100));
+
+        let err = cancel_order(deps.as_mut(), mock_env(), mock_info("intruder", &[]), token_id.clone(), NFT.to_string()).unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        let err = update_order(deps.as_mut(), mock_env(), mock_info("intruder", &[]), token_id.clone(), NFT.to_string(), uluna(200), at(expire_at), None, None).unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        let err = safe_execute_order(deps.as_mut(), mock_env(), mock_info("seller", &[]), token_id.clone(), NFT.to_string(), uluna(100)).unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        let err = safe_execute_order(deps.as_mut(), mock_env(), mock_info("buyer", &[]), token_id.clone(), NFT.to_string(), uluna(99)).unwrap_err();
+        assert_eq!(err, ContractError::InvalidPrice {});
+        let err = create_bid(deps.as_mut(), mock_env(), mock_info("bidder", &[]), token_id.clone(), NFT.to_string(), uluna(0), at(expire_at)).unwrap_err();
+        assert_eq!(err, ContractError::ZeroBidAmount {});
+        let err = create_bid(deps.as_mut(), mock_env(), mock_info("bidder", &[]), token_id.clone(), NFT.to_string(), uluna(100), at(1)).unwrap_err();
+        assert_eq!(err, ContractError::Expired {});
+
+        create_bid(deps.as_mut(), mock_env(), mock_info("bidder", &coins(100, "uluna")), token_id.clone(), NFT.to_string(), uluna(100), at(expire_at)).unwrap();
+        let err = cancel_bid(deps.as_mut(), mock_env(), mock_info("intruder", &[]), token_id.clone(), NFT.to_string()).unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        assert!(bids().may_load(&deps.storage, (&token_id, NFT)).unwrap().is_some());
+    }
+
+    #[test]
+    fn tax_query_failure_is_reported() {
+        let mut deps = mock_dependencies(&[]);
+        do_instantiate(deps.as_mut());
+        let token_id = "1".to_string();
+        let uusd = |amount: u128| Asset {
+            amount: Uint128::from(amount),
+            info: AssetInfo::NativeToken { denom: "uusd".to_string() }
+        };
+        let expire_at = mock_env().block.time.seconds() + 3600;
+        list(deps.as_mut(), "seller", &token_id, uusd(100));
+        create_bid(deps.as_mut(), mock_env(), mock_info("bidder", &coins(100, "uusd")), token_id.clone(), NFT.to_string(), uusd(100), at(expire_at)).unwrap();
+
+        let err = cancel_bid(deps.as_mut(), mock_env(), mock_info("bidder", &[]), token_id.clone(), NFT.to_string()).unwrap_err();
+        assert!(matches!(err, ContractError::TaxComputationFailed { ref denom, .. } if denom == "uusd"));
+
+        deps.querier.with_tax(Decimal::permille(5), &[("uusd", 1_000_000)]);
+        let res = cancel_bid(deps.as_mut(), mock_env(), mock_info("bidder", &[]), token_id, NFT.to_string()).unwrap();
+        assert_eq!(res.messages.len(), 1);
+    }
+
+    #[test]
+    fn simulate_buy_matches_settlement() {
+        let mut deps = mock_dependencies(&[]);
+        do_instantiate(deps.as_mut());
+        deps.querier.with_tax(Decimal::percent(1), &[("uusd", 1_000_000)]);
+        let token_id = "1".to_string();
+        let price = Asset {
+            amount: Uint128::from(10_100u128),
+            info: AssetInfo::NativeToken { denom: "uusd".to_string() }
+        };
+        list(deps.as_mut(), "seller", &token_id, price.clone());
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::SimulateBuy { token_id: token_id.clone(), nft_address: NFT.to_string() }).unwrap();
+        let settlement: SettlementResponse = from_binary(&res).unwrap();
+        // 2% fee of 10100 = 202 stays in the contract, seller gets 9898 of which 1% tax is taken on the way out
+        assert_eq!(settlement, SettlementResponse {
+            price: price.clone(),
+            fee_rate: Decimal::percent(2),
+            marketplace_fee: Uint128::from(202u128),
+            royalty: Uint128::zero(),
+            tax: Uint128::from(98u128),
+            seller_net: Uint128::from(9800u128),
+            seller_payout: None,
+            buyer_total: Uint128::from(10_100u128)
+        });
+
+        let res = safe_execute_order(deps.as_mut(), mock_env(), mock_info("buyer", &coins(10_100, "uusd")), token_id, NFT.to_string(), price).unwrap();
+        assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+            to_address: "seller".to_string(),
+            amount: vec![Coin { denom: "uusd".to_string(), amount: settlement.seller_net }]
+        }));
+    }
+
+    #[test]
+    fn simulate_accept_bid_uses_bid_price() {
+        let mut deps = mock_dependencies(&[]);
+        do_instantiate(deps.as_mut());
+        let token_id = "1".to_string();
+        let expire_at = mock_env().block.time.seconds() + 3600;
+        list(deps.as_mut(), "seller", &token_id, uluna(100));
+        create_bid(deps.as_mut(), mock_env(), mock_info("bidder", &coins(150, "uluna")), token_id.clone(), NFT.to_string(), uluna(150), at(expire_at)).unwrap();
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::SimulateAcceptBid { token_id, nft_address: NFT.to_string() }).unwrap();
+        let settlement: SettlementResponse = from_binary(&res).unwrap();
+        assert_eq!(settlement.marketplace_fee, Uint128::from(3u128));
+        assert_eq!(settlement.tax, Uint128::zero());
+        assert_eq!(settlement.seller_net, Uint128::from(147u128));
+    }
+
+    fn sent_total(res: &Response<TerraMsgWrapper>) -> Uint128 {
+        res.messages.iter().map(|m| match &m.msg {
+            CosmosMsg::Bank(BankMsg::Send { amount, .. }) => amount[0].amount,
+            _ => Uint128::zero()
+        }).sum()
+    }
+
+    #[test]
+    fn seller_bears_tax_within_escrow() {
+        let mut deps = mock_dependencies(&[]);
+        do_instantiate(deps.as_mut());
+        deps.querier.with_tax(Decimal::percent(1), &[("uusd", 50)]);
+        let token_id = "1".to_string();
+        let price = Asset {
+            amount: Uint128::from(100_000u128),
+            info: AssetInfo::NativeToken { denom: "uusd".to_string() }
+        };
+        list(deps.as_mut(), "seller", &token_id, price.clone());
+
+        let err = safe_execute_order(deps.as_mut(), mock_env(), mock_info("buyer", &coins(99_999, "uusd")), token_id.clone(), NFT.to_string(), price.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Std(_)));
+
+        let res = safe_execute_order(deps.as_mut(), mock_env(), mock_info("buyer", &coins(100_000, "uusd")), token_id, NFT.to_string(), price).unwrap();
+        // the 2000 fee accrues untaxed, seller 98000 hits the 50 cap
+        assert_eq!(sent_total(&res), Uint128::from(98_000u128 - 50));
+        assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+            to_address: "seller".to_string(),
+            amount: coins(97_950, "uusd")
+        }));
+        assert_eq!(ACCRUED_FEES.load(&deps.storage, b"uusd").unwrap().amount, Uint128::from(2000u128));
+    }
+
+    #[test]
+    fn buyer_bears_tax_on_top() {
+        let mut deps = mock_dependencies(&[]);
+        let instantiate_msg = InstantiateMsg {
+            decimals: Uint128::from(6u128),
+            name: "nftmx".to_string(),
+            symbol: "NFTMX".to_string(),
+            accepted_assets: vec![native("uluna"), native("uusd")],
+            owner_cut_rate: Decimal::percent(2),
+            buyer_pays_tax: true
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("owner", &[]), instantiate_msg).unwrap();
+        deps.querier.with_tax(Decimal::percent(1), &[("uusd", 50)]);
+        let token_id = "1".to_string();
+        let price = Asset {
+            amount: Uint128::from(100_000u128),
+            info: AssetInfo::NativeToken { denom: "uusd".to_string() }
+        };
+        list(deps.as_mut(), "seller", &token_id, price.clone());
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::SimulateBuy { token_id: token_id.clone(), nft_address: NFT.to_string() }).unwrap();
+        let settlement: SettlementResponse = from_binary(&res).unwrap();
+        assert_eq!(settlement.tax, Uint128::from(50u128));
+        assert_eq!(settlement.seller_net, Uint128::from(98_000u128));
+        assert_eq!(settlement.buyer_total, Uint128::from(100_050u128));
 
-        let zero_price_err = _create_order(
-            deps.as_mut(),
-            mock_env(),
-            info.clone(),
-            token_id.clone(),
-            nft_address.clone(),
-            zeroprice.clone(),
-            expiration
-        ).unwrap_err();
-        assert_eq!(zero_price_err, ContractError::InvalidPrice {});
+        let err = safe_execute_order(deps.as_mut(), mock_env(), mock_info("buyer", &coins(100_000, "uusd")), token_id.clone(), NFT.to_string(), price.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Std(_)));
 
-        let expired_err = _create_order(
-            deps.as_mut(),
-            mock_env(),
-            info.clone(),
-            token_id.clone(),
-            nft_address.clone(),
-            price.clone(),
-            expired_expiration
-        ).unwrap_err();
-        assert_eq!(expired_err, ContractError::InvalidExpiration {});
+        let res = safe_execute_order(deps.as_mut(), mock_env(), mock_info("buyer", &coins(100_050, "uusd")), token_id, NFT.to_string(), price).unwrap();
+        assert_eq!(sent_total(&res), Uint128::from(98_000u128));
+        assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+            to_address: "seller".to_string(),
+            amount: coins(98_000, "uusd")
+        }));
+    }
 
-        let res = _create_order(
-            deps.as_mut(),
-            mock_env(),
-            info.clone(),
-            token_id.clone(),
-            nft_address.clone(),
-            price.clone(),
-            expiration
-        ).unwrap();
-        assert_eq!(res, Response::new()
-            .add_attribute("action", "create_order")
-            .add_attribute("token_id", token_id)
-            .add_attribute("nft_address", nft_address)
-            .add_attribute("seller", info.sender)
-            .add_attribute("price", price.amount)
-        );
+    #[test]
+    fn accepted_assets_are_enforced() {
+        let mut deps = mock_dependencies(&[]);
+        do_instantiate(deps.as_mut());
+        deps.querier.with_nft_owner(NFT, "1", "seller");
+        let expire_at = mock_env().block.time.seconds() + 3600;
+        let ukrw = Asset { amount: Uint128::from(100u128), info: native("ukrw") };
+
+        let err = create_order(deps.as_mut(), mock_env(), mock_info("seller", &[]), "1".to_string(), NFT.to_string(), ukrw.clone(), at(expire_at), None, None).unwrap_err();
+        assert_eq!(err, ContractError::AssetNotAccepted { asset: "ukrw".to_string() });
+
+        let err = set_collection_accepted_assets(deps.as_mut(), mock_env(), mock_info("seller", &[]), NFT.to_string(), Some(vec![native("ukrw")])).unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        set_collection_accepted_assets(deps.as_mut(), mock_env(), mock_info("owner", &[]), NFT.to_string(), Some(vec![native("ukrw")])).unwrap();
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::AcceptedAssets { nft_address: Some(NFT.to_string()) }).unwrap();
+        assert_eq!(from_binary::<AcceptedAssetsResponse>(&res).unwrap().assets, vec![native("ukrw")]);
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::AcceptedAssets { nft_address: None }).unwrap();
+        assert_eq!(from_binary::<AcceptedAssetsResponse>(&res).unwrap().assets, vec![native("uluna"), native("uusd")]);
+
+        let err = create_order(deps.as_mut(), mock_env(), mock_info("seller", &[]), "1".to_string(), NFT.to_string(), uluna(100), at(expire_at), None, None).unwrap_err();
+        assert_eq!(err, ContractError::AssetNotAccepted { asset: "uluna".to_string() });
+        create_order(deps.as_mut(), mock_env(), mock_info("seller", &[]), "1".to_string(), NFT.to_string(), ukrw.clone(), at(expire_at), None, None).unwrap();
+
+        let err = create_bid(deps.as_mut(), mock_env(), mock_info("bidder", &coins(100, "uluna")), "1".to_string(), NFT.to_string(), uluna(100), at(expire_at)).unwrap_err();
+        assert_eq!(err, ContractError::AssetNotAccepted { asset: "uluna".to_string() });
+        create_bid(deps.as_mut(), mock_env(), mock_info("bidder", &coins(100, "ukrw")), "1".to_string(), NFT.to_string(), ukrw, at(expire_at)).unwrap();
+
+        set_collection_accepted_assets(deps.as_mut(), mock_env(), mock_info("owner", &[]), NFT.to_string(), None).unwrap();
+        let err = update_accepted_assets(deps.as_mut(), mock_env(), mock_info("seller", &[]), vec![]).unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        // cw20 tokens could be listed for but never collected
+        let cw20 = AssetInfo::Token { contract_addr: "terra1token".to_string() };
+        let err = update_accepted_assets(deps.as_mut(), mock_env(), mock_info("owner", &[]), vec![native("uusd"), cw20.clone()]).unwrap_err();
+        assert_eq!(err, ContractError::UnsupportedAsset { asset: cw20.to_string() });
+        update_accepted_assets(deps.as_mut(), mock_env(), mock_info("owner", &[]), vec![native("uusd")]).unwrap();
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::AcceptedAssets { nft_address: Some(NFT.to_string()) }).unwrap();
+        assert_eq!(from_binary::<AcceptedAssetsResponse>(&res).unwrap().assets, vec![native("uusd")]);
+    }
+
+    #[test]
+    fn payout_swap_respects_min_received() {
+        let mut deps = mock_dependencies(&[]);
+        do_instantiate(deps.as_mut());
+        deps.querier.with_tax(Decimal::zero(), &[]);
+        deps.querier.with_swap_rate("uusd", "ukrw", Decimal::from_ratio(1200u128, 1u128));
+        let token_id = "1".to_string();
+        let expire_at = mock_env().block.time.seconds() + 3600;
+        let price = Asset { amount: Uint128::from(1000u128), info: native("uusd") };
+
+        let swap_to = |denom: &str, min_received: u128| Some(PayoutSwap { denom: denom.to_string(), min_received: Uint128::from(min_received) });
+        let err = _create_order(deps.as_mut(), mock_env(), mock_info("seller", &[]), token_id.clone(), NFT.to_string(), price.clone(), at(expire_at), swap_to("uusd", 0), None).unwrap_err();
+        assert_eq!(err, ContractError::InvalidPayoutSwap {});
+        _create_order(deps.as_mut(), mock_env(), mock_info("seller", &[]), token_id.clone(), NFT.to_string(), price.clone(), at(expire_at), swap_to("ukrw", 1_200_000), None).unwrap();
+
+        // 980 uusd of proceeds only fetch 1_176_000 ukrw
+        let err = safe_execute_order(deps.as_mut(), mock_env(), mock_info("buyer", &coins(1000, "uusd")), token_id.clone(), NFT.to_string(), price.clone()).unwrap_err();
+        assert_eq!(err, ContractError::PayoutBelowMinimum { min_received: Uint128::from(1_200_000u128), received: Uint128::from(1_176_000u128) });
+
+        _update_order(deps.as_mut(), mock_env(), mock_info("seller", &[]), token_id.clone(), NFT.to_string(), price.clone(), at(expire_at), swap_to("ukrw", 1_100_000), None).unwrap();
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::SimulateBuy { token_id: token_id.clone(), nft_address: NFT.to_string() }).unwrap();
+        let settlement: SettlementResponse = from_binary(&res).unwrap();
+        assert_eq!(settlement.seller_payout, Some(Coin { denom: "ukrw".to_string(), amount: Uint128::from(1_176_000u128) }));
+
+        let res = safe_execute_order(deps.as_mut(), mock_env(), mock_info("buyer", &coins(1000, "uusd")), token_id, NFT.to_string(), price).unwrap();
+        assert_eq!(res.messages[0].msg, create_swap_send_msg("seller".to_string(), Coin { denom: "uusd".to_string(), amount: Uint128::from(980u128) }, "ukrw".to_string()));
+    }
+
+    fn nft_transfer(nft_address: &str, token_id: &str, recipient: &str) -> CosmosMsg<TerraMsgWrapper> {
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: nft_address.to_string(),
+            msg: to_binary(&Cw721ExecuteMsg::TransferNft { recipient: recipient.to_string(), token_id: token_id.to_string() }).unwrap(),
+            funds: vec![]
+        })
+    }
+
+    #[test]
+    fn bundle_sells_as_a_unit() {
+        const OTHER_NFT: &str = "terra1other";
+        let mut deps = mock_dependencies(&[]);
+        do_instantiate(deps.as_mut());
+        deps.querier.with_nft_owner(NFT, "1", "seller");
+        deps.querier.with_nft_owner(NFT, "2", "seller");
+        deps.querier.with_nft_owner(OTHER_NFT, "7", "someone_else");
+        let expire_at = mock_env().block.time.seconds() + 3600;
+        let item = |nft_address: &str, token_id: &str| NftItemMsg { nft_address: nft_address.to_string(), token_id: token_id.to_string() };
+
+        let err = create_bundle(deps.as_mut(), mock_env(), mock_info("seller", &[]), vec![item(NFT, "1"), item(OTHER_NFT, "7")], uluna(1000), at(expire_at), None).unwrap_err();
+        assert_eq!(err, ContractError::NoOwner {});
+        let err = create_bundle(deps.as_mut(), mock_env(), mock_info("seller", &[]), vec![item(NFT, "1"), item(NFT, "1")], uluna(1000), at(expire_at), None).unwrap_err();
+        assert_eq!(err, ContractError::InvalidBundle {});
+        let err = create_bundle(deps.as_mut(), mock_env(), mock_info("seller", &[]), vec![], uluna(1000), at(expire_at), None).unwrap_err();
+        assert_eq!(err, ContractError::InvalidBundle {});
+
+        deps.querier.with_nft_owner(OTHER_NFT, "7", "seller");
+        let res = create_bundle(deps.as_mut(), mock_env(), mock_info("seller", &[]), vec![item(NFT, "1"), item(OTHER_NFT, "7")], uluna(1000), at(expire_at), None).unwrap();
+        assert_eq!(res.attributes[1].value, "1");
+        let expire_height = mock_env().block.height + 100;
+        create_bundle(deps.as_mut(), mock_env(), mock_info("seller", &[]), vec![item(NFT, "2")], uluna(500), Expiration::AtHeight(expire_height), None).unwrap();
+
+        let err = buy_bundle(deps.as_mut(), mock_env(), mock_info("buyer", &coins(999, "uluna")), 1, uluna(999)).unwrap_err();
+        assert_eq!(err, ContractError::InvalidPrice {});
+        let res = buy_bundle(deps.as_mut(), mock_env(), mock_info("buyer", &coins(1000, "uluna")), 1, uluna(1000)).unwrap();
+        let msgs: Vec<_> = res.messages.into_iter().map(|m| m.msg).collect();
+        assert_eq!(msgs, vec![
+            CosmosMsg::Bank(BankMsg::Send { to_address: "seller".to_string(), amount: coins(980, "uluna") }),
+            nft_transfer(NFT, "1", "buyer"),
+            nft_transfer(OTHER_NFT, "7", "buyer"),
+        ]);
+        let err = buy_bundle(deps.as_mut(), mock_env(), mock_info("buyer", &coins(1000, "uluna")), 1, uluna(1000)).unwrap_err();
+        assert_eq!(err, ContractError::NoBundle {});
+        let mut env = mock_env();
+        env.block.height = expire_height;
+        let err = buy_bundle(deps.as_mut(), env, mock_info("buyer", &coins(500, "uluna")), 2, uluna(500)).unwrap_err();
+        assert_eq!(err, ContractError::Expired {});
+
+        let err = cancel_bundle(deps.as_mut(), mock_env(), mock_info("buyer", &[]), 2).unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        let res = cancel_bundle(deps.as_mut(), mock_env(), mock_info("seller", &[]), 2).unwrap();
+        assert_eq!(res.messages[0].msg, nft_transfer(NFT, "2", "seller"));
+        assert!(query(deps.as_ref(), mock_env(), QueryMsg::GetBundle { bundle_id: 2 }).is_err());
+    }
+
+    #[test]
+    fn batch_orders_modes() {
+        let mut deps = mock_dependencies(&[]);
+        do_instantiate(deps.as_mut());
+        deps.querier.with_nft_owner(NFT, "1", "seller");
+        deps.querier.with_nft_owner(NFT, "2", "seller");
+        deps.querier.with_nft_owner(NFT, "3", "someone_else");
+        let expire_at = mock_env().block.time.seconds() + 3600;
+        let order = |token_id: &str, amount: u128| OrderMsg {
+            token_id: token_id.to_string(),
+            nft_address: NFT.to_string(),
+            price: uluna(amount),
+            expire_at: at(expire_at),
+            payout_swap: None,
+            reserved_for: None
+        };
+
+        let err = batch_create_orders(deps.as_mut(), mock_env(), mock_info("seller", &[]), vec![order("1", 100), order("3", 100)], BatchMode::AllOrNothing).unwrap_err();
+        assert_eq!(err, ContractError::NoOwner {});
+
+        let res = batch_create_orders(deps.as_mut(), mock_env(), mock_info("seller", &[]), vec![order("1", 100), order("3", 100), order("2", 200)], BatchMode::BestEffort).unwrap();
+        let items: Vec<_> = res.attributes.iter().filter(|a| a.key == "item").map(|a| a.value.clone()).collect();
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0], format!("{}/1:ok", NFT));
+        assert!(items[1].starts_with(&format!("{}/3:failed:", NFT)));
+        assert_eq!(items[2], format!("{}/2:ok", NFT));
+        assert!(res.attributes.iter().any(|a| a.key == "succeeded" && a.value == "2"));
+        assert!(orders().may_load(&deps.storage, ("2", NFT)).unwrap().is_some());
+        assert!(orders().may_load(&deps.storage, ("3", NFT)).unwrap().is_none());
+
+        batch_update_orders(deps.as_mut(), mock_env(), mock_info("seller", &[]), vec![order("1", 150), order("2", 250)], BatchMode::AllOrNothing).unwrap();
+        assert_eq!(orders().load(&deps.storage, ("2", NFT)).unwrap().price, uluna(250));
+
+        let item = |token_id: &str| NftItemMsg { nft_address: NFT.to_string(), token_id: token_id.to_string() };
+        let res = batch_cancel_orders(deps.as_mut(), mock_env(), mock_info("seller", &[]), vec![item("1"), item("3"), item("2")], BatchMode::BestEffort).unwrap();
+        assert_eq!(res.messages.len(), 2);
+        assert!(res.attributes.iter().any(|a| a.key == "failed" && a.value == "1"));
+        assert!(orders().may_load(&deps.storage, ("1", NFT)).unwrap().is_none());
+    }
+
+    #[test]
+    fn batch_buy_takes_one_combined_payment() {
+        const OTHER_NFT: &str = "terra1other";
+        let mut deps = mock_dependencies(&[]);
+        do_instantiate(deps.as_mut());
+        deps.querier.with_nft_owner(NFT, "1", "seller");
+        deps.querier.with_nft_owner(OTHER_NFT, "7", "seller");
+        let expire_at = mock_env().block.time.seconds() + 3600;
+        list(deps.as_mut(), "seller", "1", uluna(100));
+        _create_order(deps.as_mut(), mock_env(), mock_info("seller", &[]), "7".to_string(), OTHER_NFT.to_string(), uluna(200), at(expire_at), None, None).unwrap();
+        let buy = |nft_address: &str, token_id: &str, amount: u128| BuyMsg {
+            token_id: token_id.to_string(),
+            nft_address: nft_address.to_string(),
+            price: uluna(amount)
+        };
+
+        let err = batch_buy(deps.as_mut(), mock_env(), mock_info("buyer", &coins(250, "uluna")), vec![buy(NFT, "1", 100), buy(OTHER_NFT, "7", 200)]).unwrap_err();
+        assert_eq!(err, ContractError::InsufficientFunds { denom: "uluna".to_string(), required: Uint128::from(300u128), sent: Uint128::from(250u128) });
+        let err = batch_buy(deps.as_mut(), mock_env(), mock_info("buyer", &coins(350, "uluna")), vec![buy(NFT, "1", 100), buy(OTHER_NFT, "7", 150)]).unwrap_err();
+        assert_eq!(err, ContractError::InvalidPrice {});
+
+        let res = batch_buy(deps.as_mut(), mock_env(), mock_info("buyer", &coins(350, "uluna")), vec![buy(NFT, "1", 100), buy(OTHER_NFT, "7", 200)]).unwrap();
+        let msgs: Vec<_> = res.messages.into_iter().map(|m| m.msg).collect();
+        assert_eq!(msgs, vec![
+            CosmosMsg::Bank(BankMsg::Send { to_address: "seller".to_string(), amount: coins(98, "uluna") }),
+            nft_transfer(NFT, "1", "buyer"),
+            CosmosMsg::Bank(BankMsg::Send { to_address: "seller".to_string(), amount: coins(196, "uluna") }),
+            nft_transfer(OTHER_NFT, "7", "buyer"),
+            CosmosMsg::Bank(BankMsg::Send { to_address: "buyer".to_string(), amount: coins(50, "uluna") }),
+        ]);
+        assert!(orders().may_load(&deps.storage, ("1", NFT)).unwrap().is_none());
+        assert!(orders().may_load(&deps.storage, ("7", OTHER_NFT)).unwrap().is_none());
+    }
+
+    #[test]
+    fn sweep_floor_buys_cheapest_within_limits() {
+        let mut deps = mock_dependencies(&[]);
+        do_instantiate(deps.as_mut());
+        for (token_id, owner) in [("1", "seller"), ("2", "seller"), ("3", "seller"), ("4", "buyer"), ("5", "seller"), ("6", "rogue")] {
+            deps.querier.with_nft_owner(NFT, token_id, owner);
+        }
+        list(deps.as_mut(), "seller", "1", uluna(300));
+        list(deps.as_mut(), "seller", "2", uluna(100));
+        list(deps.as_mut(), "seller", "3", uluna(200));
+        list(deps.as_mut(), "buyer", "4", uluna(50));
+        // blocked floor listings are skipped rather than failing the sweep
+        list(deps.as_mut(), "seller", "5", uluna(10));
+        list(deps.as_mut(), "rogue", "6", uluna(20));
+        set_token_blocked(deps.as_mut(), mock_env(), mock_info("owner", &[]), "5".to_string(), NFT.to_string(), true).unwrap();
+        set_address_blocked(deps.as_mut(), mock_env(), mock_info("owner", &[]), "rogue".to_string(), true).unwrap();
+
+        let res = sweep_floor(deps.as_mut(), mock_env(), mock_info("buyer", &coins(400, "uluna")), NFT.to_string(), 5, uluna(350)).unwrap();
+        let transfers: Vec<_> = res.messages.iter().map(|m| m.msg.clone())
+            .filter(|m| matches!(m, CosmosMsg::Wasm(_))).collect();
+        assert_eq!(transfers, vec![nft_transfer(NFT, "2", "buyer"), nft_transfer(NFT, "3", "buyer")]);
+        assert_eq!(res.messages.last().unwrap().msg, CosmosMsg::Bank(BankMsg::Send { to_address: "buyer".to_string(), amount: coins(100, "uluna") }));
+        assert!(res.attributes.iter().any(|a| a.key == "total" && a.value == "300"));
+        assert!(orders().may_load(&deps.storage, ("1", NFT)).unwrap().is_some());
+        assert!(orders().may_load(&deps.storage, ("4", NFT)).unwrap().is_some());
+
+        let res = sweep_floor(deps.as_mut(), mock_env(), mock_info("other", &coins(1000, "uluna")), NFT.to_string(), 1, uluna(1000)).unwrap();
+        assert!(res.attributes.iter().any(|a| a.key == "count" && a.value == "1"));
+        assert_eq!(res.messages[1].msg, nft_transfer(NFT, "4", "other"));
+        assert!(orders().may_load(&deps.storage, ("5", NFT)).unwrap().is_some());
+        assert!(orders().may_load(&deps.storage, ("6", NFT)).unwrap().is_some());
+    }
+
+    #[test]
+    fn sales_are_recorded_and_paginated() {
+        let mut deps = mock_dependencies(&[]);
+        do_instantiate(deps.as_mut());
+        for token_id in ["1", "2", "3"] {
+            deps.querier.with_nft_owner(NFT, token_id, "seller");
+            list(deps.as_mut(), "seller", token_id, uluna(100));
+        }
+        for (token_id, buyer) in [("1", "alice"), ("2", "bob"), ("3", "alice")] {
+            safe_execute_order(deps.as_mut(), mock_env(), mock_info(buyer, &coins(100, "uluna")), token_id.to_string(), NFT.to_string(), uluna(100)).unwrap();
+        }
+
+        let sale: Sale = from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::GetSale { sale_id: 2 }).unwrap()).unwrap();
+        assert_eq!(sale.token_id, "2");
+        assert_eq!(sale.buyer, Addr::unchecked("bob"));
+        assert_eq!(sale.marketplace_fee, Uint128::from(2u128));
+        assert_eq!(sale.sale_type, SaleType::Order);
+        assert_eq!(sale.time, mock_env().block.time.seconds());
+
+        let ids = |filter: Option<SaleFilter>, start_after: Option<u64>, limit: Option<u32>| {
+            let res: SalesResponse = from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Sales { filter, start_after, limit }).unwrap()).unwrap();
+            res.sales.into_iter().map(|s| s.sale_id).collect::<Vec<_>>()
+        };
+        assert_eq!(ids(None, None, None), vec![3, 2, 1]);
+        assert_eq!(ids(None, Some(3), Some(1)), vec![2]);
+        assert_eq!(ids(Some(SaleFilter::Buyer { address: "alice".to_string() }), None, None), vec![3, 1]);
+        assert_eq!(ids(Some(SaleFilter::Buyer { address: "alice".to_string() }), Some(3), None), vec![1]);
+        assert_eq!(ids(Some(SaleFilter::Seller { address: "seller".to_string() }), None, Some(2)), vec![3, 2]);
+        assert_eq!(ids(Some(SaleFilter::Token { nft_address: NFT.to_string(), token_id: "2".to_string() }), None, None), vec![2]);
+        assert_eq!(ids(Some(SaleFilter::Collection { nft_address: "terra1other".to_string() }), None, None), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn collection_stats_track_listings_and_volume() {
+        const OTHER_NFT: &str = "terra1other";
+        let mut deps = mock_dependencies(&[]);
+        do_instantiate(deps.as_mut());
+        for token_id in ["1", "2", "3"] {
+            deps.querier.with_nft_owner(NFT, token_id, "seller");
+        }
+        deps.querier.with_nft_owner(OTHER_NFT, "7", "seller");
+        list(deps.as_mut(), "seller", "1", uluna(100));
+        list(deps.as_mut(), "seller", "2", uluna(300));
+        list(deps.as_mut(), "seller", "2", uluna(200));
+        list(deps.as_mut(), "seller", "3", uluna(50));
+        let expire_at = mock_env().block.time.seconds() + 3600;
+        _create_order(deps.as_mut(), mock_env(), mock_info("seller", &[]), "7".to_string(), OTHER_NFT.to_string(), uluna(500), at(expire_at), None, None).unwrap();
+
+        safe_execute_order(deps.as_mut(), mock_env(), mock_info("buyer", &coins(50, "uluna")), "3".to_string(), NFT.to_string(), uluna(50)).unwrap();
+        safe_execute_order(deps.as_mut(), mock_env(), mock_info("buyer", &coins(200, "uluna")), "2".to_string(), NFT.to_string(), uluna(200)).unwrap();
+        cancel_order(deps.as_mut(), mock_env(), mock_info("seller", &[]), "1".to_string(), NFT.to_string()).unwrap();
+        list(deps.as_mut(), "seller", "1", uluna(120));
+        safe_execute_order(deps.as_mut(), mock_env(), mock_info("buyer", &coins(500, "uluna")), "7".to_string(), OTHER_NFT.to_string(), uluna(500)).unwrap();
+
+        let stats: CollectionStatsResponse = from_binary(&query(deps.as_ref(), mock_env(),
+            QueryMsg::CollectionStats { nft_address: NFT.to_string() }).unwrap()).unwrap();
+        assert_eq!(stats, CollectionStatsResponse {
+            nft_address: NFT.to_string(),
+            volume: vec![uluna(250)],
+            sales_count: 2,
+            last_sale: Some(uluna(200)),
+            floor: vec![uluna(120)],
+            listed_count: 1
+        });
+
+        let top = |start_after: Option<String>, limit: Option<u32>| {
+            let res: TopCollectionsResponse = from_binary(&query(deps.as_ref(), mock_env(),
+                QueryMsg::TopCollections { asset_info: native("uluna"), start_after, limit }).unwrap()).unwrap();
+            res.collections.into_iter().map(|c| (c.nft_address.to_string(), c.volume.amount.u128())).collect::<Vec<_>>()
+        };
+        assert_eq!(top(None, None), vec![(OTHER_NFT.to_string(), 500), (NFT.to_string(), 250)]);
+        assert_eq!(top(Some(OTHER_NFT.to_string()), Some(1)), vec![(NFT.to_string(), 250)]);
+
+        // a floor hidden behind too many private listings is left out
+        for i in 0..MAX_FLOOR_SCAN {
+            _create_order(deps.as_mut(), mock_env(), mock_info("seller", &[]), format!("r{}", i), NFT.to_string(), uluna(10), at(expire_at), None, Some("friend".to_string())).unwrap();
+        }
+        let stats: CollectionStatsResponse = from_binary(&query(deps.as_ref(), mock_env(),
+            QueryMsg::CollectionStats { nft_address: NFT.to_string() }).unwrap()).unwrap();
+        assert!(stats.floor.is_empty());
+    }
+
+    #[test]
+    fn twap_averages_clamped_sale_prices() {
+        let mut deps = mock_dependencies(&[]);
+        do_instantiate(deps.as_mut());
+        let start = mock_env().block.time.seconds();
+        let env_at = |seconds: u64| {
+            let mut env = mock_env();
+            env.block.time = Timestamp::from_seconds(start + seconds);
+            env
+        };
+        for (token_id, price) in [("1", 100), ("2", 1000), ("3", 100)] {
+            deps.querier.with_nft_owner(NFT, token_id, "seller");
+            list(deps.as_mut(), "seller", token_id, uluna(price));
+        }
+        for (token_id, price, seconds) in [("1", 100, 0), ("2", 1000, 100), ("3", 100, 200)] {
+            safe_execute_order(deps.as_mut(), env_at(seconds), mock_info("buyer", &coins(price, "uluna")), token_id.to_string(), NFT.to_string(), uluna(price)).unwrap();
+        }
+
+        let twap = |window_seconds: u64| {
+            let res: TwapResponse = from_binary(&query(deps.as_ref(), env_at(300),
+                QueryMsg::Twap { nft_address: NFT.to_string(), window_seconds }).unwrap()).unwrap();
+            res.prices
+        };
+        // the 1000 wash trade only counts as 120
+        assert_eq!(twap(300), vec![TwapPrice { price: uluna((100 * 100 + 120 * 100 + 100 * 100) / 300), observed_seconds: 300 }]);
+        assert_eq!(twap(150), vec![TwapPrice { price: uluna((120 * 50 + 100 * 100) / 150), observed_seconds: 150 }]);
+        assert_eq!(twap(1000), twap(300));
+        assert_eq!(twap(0), vec![TwapPrice { price: uluna(100), observed_seconds: 0 }]);
+    }
+
+    #[test]
+    fn user_stats_and_leaderboard() {
+        let mut deps = mock_dependencies(&[]);
+        do_instantiate(deps.as_mut());
+        for (token_id, price) in [("1", 100), ("2", 300), ("3", 50)] {
+            deps.querier.with_nft_owner(NFT, token_id, "seller");
+            list(deps.as_mut(), "seller", token_id, uluna(price));
+        }
+        for (token_id, buyer, price) in [("1", "alice", 100), ("2", "bob", 300), ("3", "alice", 50)] {
+            safe_execute_order(deps.as_mut(), mock_env(), mock_info(buyer, &coins(price, "uluna")), token_id.to_string(), NFT.to_string(), uluna(price)).unwrap();
+        }
+
+        let stats: UserStatsResponse = from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::UserStats { address: "seller".to_string() }).unwrap()).unwrap();
+        assert_eq!((stats.items_bought, stats.items_sold), (0, 3));
+        assert_eq!(stats.volumes, vec![UserVolume {
+            address: Addr::unchecked("seller"),
+            info: native("uluna"),
+            bought: Uint128::zero(),
+            sold: Uint128::from(450u128),
+            fees_paid: Uint128::from(9u128),
+            royalties_paid: Uint128::zero()
+        }]);
+        let stats: UserStatsResponse = from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::UserStats { address: "alice".to_string() }).unwrap()).unwrap();
+        assert_eq!((stats.items_bought, stats.items_sold, stats.volumes[0].bought), (2, 0, Uint128::from(150u128)));
+
+        let leaders = |start_after: Option<String>, limit: Option<u32>| {
+            let res: LeaderboardResponse = from_binary(&query(deps.as_ref(), mock_env(),
+                QueryMsg::Leaderboard { asset_info: native("uluna"), start_after, limit }).unwrap()).unwrap();
+            res.entries.into_iter().map(|e| e.address.to_string()).collect::<Vec<_>>()
+        };
+        assert_eq!(leaders(None, None), vec!["seller", "bob", "alice"]);
+        assert_eq!(leaders(Some("seller".to_string()), Some(1)), vec!["bob"]);
+    }
+
+    #[test]
+    fn curated_collections_gate_listings_and_pay_royalties() {
+        let mut deps = mock_dependencies(&[]);
+        do_instantiate(deps.as_mut());
+        deps.querier.with_nft_owner(NFT, "1", "seller");
+        let expire_at = mock_env().block.time.seconds() + 3600;
+        let create = |deps: DepsMut| _create_order(deps, mock_env(), mock_info("seller", &[]), "1".to_string(), NFT.to_string(), uluna(1000), at(expire_at), None, None);
+        let curate = |deps: DepsMut, sender: &str, status: CollectionStatus| set_collection(deps, mock_env(), mock_info(sender, &[]),
+            NFT.to_string(), "Punks".to_string(), "creator".to_string(), Decimal::percent(5), status);
+
+        let err = curate(deps.as_mut(), "curator", CollectionStatus::Verified).unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        set_curators(deps.as_mut(), mock_env(), mock_info("owner", &[]), vec!["curator".to_string()]).unwrap();
+        let err = set_collection(deps.as_mut(), mock_env(), mock_info("curator", &[]), NFT.to_string(), "Punks".to_string(),
+            "creator".to_string(), Decimal::percent(11), CollectionStatus::Verified).unwrap_err();
+        assert_eq!(err, ContractError::InvalidRoyalty { max: Decimal::percent(10) });
+
+        set_require_verified(deps.as_mut(), mock_env(), mock_info("owner", &[]), true).unwrap();
+        assert_eq!(create(deps.as_mut()).unwrap_err(), ContractError::CollectionNotVerified {});
+        curate(deps.as_mut(), "curator", CollectionStatus::Banned).unwrap();
+        assert_eq!(create(deps.as_mut()).unwrap_err(), ContractError::CollectionBanned {});
+        curate(deps.as_mut(), "curator", CollectionStatus::Verified).unwrap();
+        create(deps.as_mut()).unwrap();
+
+        let res: OrderResponse = from_binary(&query(deps.as_ref(), mock_env(),
+            QueryMsg::GetOrder { token_id: "1".to_string(), nft_address: NFT.to_string() }).unwrap()).unwrap();
+        assert!(res.verified);
+        assert_eq!(res.collection.unwrap().creator, Addr::unchecked("creator"));
+
+        let res = safe_execute_order(deps.as_mut(), mock_env(), mock_info("buyer", &coins(1000, "uluna")), "1".to_string(), NFT.to_string(), uluna(1000)).unwrap();
+        let msgs: Vec<_> = res.messages.into_iter().map(|m| m.msg).collect();
+        assert_eq!(msgs, vec![
+            CosmosMsg::Bank(BankMsg::Send { to_address: "creator".to_string(), amount: coins(50, "uluna") }),
+            CosmosMsg::Bank(BankMsg::Send { to_address: "seller".to_string(), amount: coins(930, "uluna") }),
+            nft_transfer(NFT, "1", "buyer"),
+        ]);
+        let sale: Sale = from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::GetSale { sale_id: 1 }).unwrap()).unwrap();
+        assert_eq!(sale.royalty, Uint128::from(50u128));
+    }
+
+    #[test]
+    fn moderators_delist_and_block() {
+        let mut deps = mock_dependencies(&[]);
+        do_instantiate(deps.as_mut());
+        deps.querier.with_nft_owner(NFT, "1", "thief");
+        deps.querier.with_nft_owner(NFT, "2", "seller");
+        let expire_at = mock_env().block.time.seconds() + 3600;
+        list(deps.as_mut(), "thief", "1", uluna(100));
+        list(deps.as_mut(), "seller", "2", uluna(100));
+        create_bid(deps.as_mut(), mock_env(), mock_info("bidder", &coins(100, "uluna")), "1".to_string(), NFT.to_string(), uluna(100), at(expire_at)).unwrap();
+
+        let force_cancel = |deps: DepsMut, sender: &str| force_cancel_order(deps, mock_env(), mock_info(sender, &[]), "1".to_string(), NFT.to_string(),
+            Some("victim".to_string()), "stolen".to_string());
+        assert_eq!(force_cancel(deps.as_mut(), "mod").unwrap_err(), ContractError::Unauthorized {});
+        set_moderators(deps.as_mut(), mock_env(), mock_info("owner", &[]), vec!["mod".to_string()]).unwrap();
+        let res = force_cancel(deps.as_mut(), "mod").unwrap();
+        let msgs: Vec<_> = res.messages.into_iter().map(|m| m.msg).collect();
+        assert_eq!(msgs, vec![
+            CosmosMsg::Bank(BankMsg::Send { to_address: "bidder".to_string(), amount: coins(100, "uluna") }),
+            nft_transfer(NFT, "1", "victim"),
+        ]);
+        assert!(orders().may_load(&deps.storage, ("1", NFT)).unwrap().is_none());
+        let delisting: Delisting = from_binary(&query(deps.as_ref(), mock_env(),
+            QueryMsg::GetDelisting { token_id: "1".to_string(), nft_address: NFT.to_string() }).unwrap()).unwrap();
+        assert_eq!((delisting.seller.as_str(), delisting.reason.as_str()), ("thief", "stolen"));
+
+        set_token_blocked(deps.as_mut(), mock_env(), mock_info("mod", &[]), "1".to_string(), NFT.to_string(), true).unwrap();
+        let err = _create_order(deps.as_mut(), mock_env(), mock_info("thief", &[]), "1".to_string(), NFT.to_string(), uluna(100), at(expire_at), None, None).unwrap_err();
+        assert_eq!(err, ContractError::TokenBlocked {});
+
+        set_address_blocked(deps.as_mut(), mock_env(), mock_info("mod", &[]), "buyer".to_string(), true).unwrap();
+        let blocked: bool = from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::IsAddressBlocked { address: "buyer".to_string() }).unwrap()).unwrap();
+        assert!(blocked);
+        let err = safe_execute_order(deps.as_mut(), mock_env(), mock_info("buyer", &coins(100, "uluna")), "2".to_string(), NFT.to_string(), uluna(100)).unwrap_err();
+        assert_eq!(err, ContractError::AddressBlocked { address: "buyer".to_string() });
+        let err = create_bid(deps.as_mut(), mock_env(), mock_info("buyer", &coins(100, "uluna")), "2".to_string(), NFT.to_string(), uluna(100), at(expire_at)).unwrap_err();
+        assert_eq!(err, ContractError::AddressBlocked { address: "buyer".to_string() });
+        set_address_blocked(deps.as_mut(), mock_env(), mock_info("mod", &[]), "buyer".to_string(), false).unwrap();
+        safe_execute_order(deps.as_mut(), mock_env(), mock_info("buyer", &coins(100, "uluna")), "2".to_string(), NFT.to_string(), uluna(100)).unwrap();
+    }
+
+    #[test]
+    fn pauses_are_per_operation_and_collection() {
+        const OTHER_NFT: &str = "terra1other";
+        let mut deps = mock_dependencies(&[]);
+        do_instantiate(deps.as_mut());
+        deps.querier.with_nft_owner(NFT, "1", "seller");
+        deps.querier.with_nft_owner(OTHER_NFT, "7", "seller");
+        let expire_at = mock_env().block.time.seconds() + 3600;
+        list(deps.as_mut(), "seller", "1", uluna(100));
+        create_bid(deps.as_mut(), mock_env(), mock_info("bidder", &coins(100, "uluna")), "1".to_string(), NFT.to_string(), uluna(100), at(expire_at)).unwrap();
+
+        set_paused(deps.as_mut(), mock_env(), mock_info("owner", &[]), Operation::ALL.to_vec()).unwrap();
+        let err = safe_execute_order(deps.as_mut(), mock_env(), mock_info("buyer", &coins(100, "uluna")), "1".to_string(), NFT.to_string(), uluna(100)).unwrap_err();
+        assert_eq!(err, ContractError::MarketplacePaused {});
+        // escrow stays withdrawable until the emergency flag is set
+        cancel_bid(deps.as_mut(), mock_env(), mock_info("bidder", &[]), "1".to_string(), NFT.to_string()).unwrap();
+        set_emergency(deps.as_mut(), mock_env(), mock_info("owner", &[]), true).unwrap();
+        let err = cancel_order(deps.as_mut(), mock_env(), mock_info("seller", &[]), "1".to_string(), NFT.to_string()).unwrap_err();
+        assert_eq!(err, ContractError::MarketplacePaused {});
+        set_emergency(deps.as_mut(), mock_env(), mock_info("owner", &[]), false).unwrap();
+        set_paused(deps.as_mut(), mock_env(), mock_info("owner", &[]), vec![]).unwrap();
+
+        set_collection_paused(deps.as_mut(), mock_env(), mock_info("owner", &[]), NFT.to_string(), vec![Operation::Buying]).unwrap();
+        let status: PauseStatusResponse = from_binary(&query(deps.as_ref(), mock_env(),
+            QueryMsg::PauseStatus { nft_address: Some(NFT.to_string()) }).unwrap()).unwrap();
+        assert_eq!(status, PauseStatusResponse { paused: vec![Operation::Buying], emergency: false });
+        let err = safe_execute_order(deps.as_mut(), mock_env(), mock_info("buyer", &coins(100, "uluna")), "1".to_string(), NFT.to_string(), uluna(100)).unwrap_err();
+        assert_eq!(err, ContractError::MarketplacePaused {});
+        create_order(deps.as_mut(), mock_env(), mock_info("seller", &[]), "7".to_string(), OTHER_NFT.to_string(), uluna(100), at(expire_at), None, None).unwrap();
+        safe_execute_order(deps.as_mut(), mock_env(), mock_info("buyer", &coins(100, "uluna")), "7".to_string(), OTHER_NFT.to_string(), uluna(100)).unwrap();
+    }
+
+    #[test]
+    fn migrate_pauses_everything_if_paused_before() {
+        let mut deps = mock_dependencies(&[]);
+        do_instantiate(deps.as_mut());
+        let raw = String::from_utf8(deps.storage.get(b"config").unwrap()).unwrap()
+            .replace("\"paused_operations\":0", "\"paused\":true");
+        deps.storage.set(b"config", raw.as_bytes());
+        migrate(deps.as_mut(), mock_env(), MigrateMsg::default()).unwrap();
+        let con = CONFIG.load(&deps.storage).unwrap();
+        assert_eq!(Operation::from_bits(con.paused_operations), Operation::ALL.to_vec());
+        assert!(!con.emergency);
+    }
+
+    #[test]
+    fn withdrawal_mode_returns_escrow() {
+        for balance in [100u128, 99] {
+            let mut deps = mock_dependencies(&coins(balance, "uluna"));
+            do_instantiate(deps.as_mut());
+            deps.querier.with_nft_owner(NFT, "1", "seller");
+            deps.querier.with_nft_owner(NFT, "2", "seller");
+            let expire_at = mock_env().block.time.seconds() + 3600;
+            list(deps.as_mut(), "seller", "1", uluna(100));
+            list(deps.as_mut(), "seller", "2", uluna(100));
+            create_bid(deps.as_mut(), mock_env(), mock_info("bidder", &coins(100, "uluna")), "1".to_string(), NFT.to_string(), uluna(100), at(expire_at)).unwrap();
+
+            let err = emergency_withdraw(deps.as_mut(), mock_env(), mock_info("anyone", &[]), None).unwrap_err();
+            assert_eq!(err, ContractError::NotInWithdrawalMode {});
+            let res = set_withdrawal_mode(deps.as_mut(), mock_env(), mock_info("owner", &[]), true).unwrap();
+            assert!(res.attributes.iter().any(|a| a.key == "solvent" && a.value == (balance == 100).to_string()));
+            let err = safe_execute_order(deps.as_mut(), mock_env(), mock_info("buyer", &coins(100, "uluna")), "2".to_string(), NFT.to_string(), uluna(100)).unwrap_err();
+            assert_eq!(err, ContractError::MarketplacePaused {});
+
+            if balance < 100 {
+                // the NFTs still go back, the short bid is credited instead of paid
+                let res = emergency_withdraw(deps.as_mut(), mock_env(), mock_info("anyone", &[]), None).unwrap();
+                let msgs: Vec<_> = res.messages.into_iter().map(|m| m.msg).collect();
+                assert_eq!(msgs, vec![nft_transfer(NFT, "1", "seller"), nft_transfer(NFT, "2", "seller")]);
+                assert!(res.attributes.iter().any(|a| a.key == "short" && a.value == "uluna"));
+                assert_eq!(CLAIMABLE.load(&deps.storage, ("bidder", b"uluna")).unwrap(), uluna(100));
+                assert!(bids().may_load(&deps.storage, ("1", NFT)).unwrap().is_none());
+                let res = withdraw(deps.as_mut(), mock_env(), mock_info("bidder", &[]), None).unwrap();
+                assert!(res.messages.is_empty());
+                deps.querier.with_contract_balance(&coins(100, "uluna"));
+                let res = withdraw(deps.as_mut(), mock_env(), mock_info("bidder", &[]), None).unwrap();
+                assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send { to_address: "bidder".to_string(), amount: coins(100, "uluna") }));
+                continue;
+            }
+            let res = emergency_withdraw(deps.as_mut(), mock_env(), mock_info("anyone", &[]), Some(1)).unwrap();
+            let msgs: Vec<_> = res.messages.into_iter().map(|m| m.msg).collect();
+            assert_eq!(msgs, vec![
+                CosmosMsg::Bank(BankMsg::Send { to_address: "bidder".to_string(), amount: coins(100, "uluna") }),
+                nft_transfer(NFT, "1", "seller"),
+            ]);
+            let res = emergency_withdraw(deps.as_mut(), mock_env(), mock_info("anyone", &[]), None).unwrap();
+            assert_eq!(res.messages[0].msg, nft_transfer(NFT, "2", "seller"));
+            assert!(orders().may_load(&deps.storage, ("2", NFT)).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn escrow_matches_holdings_after_random_operations() {
+        let mut deps = mock_dependencies(&[]);
+        do_instantiate(deps.as_mut());
+        let expire_at = mock_env().block.time.seconds() + 3600;
+        let tokens = ["1", "2", "3", "4"];
+        let bidders = ["alice", "bob", "carol"];
+        // plain LCG so the sequence is reproducible without extra dependencies
+        let mut seed: u64 = 0x5eed;
+        let mut next = |bound: u64| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 33) % bound
+        };
+        let mut held = Uint128::zero();
+        let mut swap_tokens = 0u64;
+        set_payout_mode(deps.as_mut(), mock_env(), mock_info("alice", &[]), PayoutMode::Pull).unwrap();
+        set_payout_mode(deps.as_mut(), mock_env(), mock_info("seller", &[]), PayoutMode::Pull).unwrap();
+
+        for _ in 0..300 {
+            let token = tokens[next(tokens.len() as u64) as usize];
+            let order = orders().may_load(&deps.storage, (token, NFT)).unwrap();
+            let bid = bids().may_load(&deps.storage, (token, NFT)).unwrap();
+            let (res, sent) = match (next(10), order, bid) {
+                (0, None, _) => {
+                    list(deps.as_mut(), "seller", token, uluna(100));
+                    continue;
+                }
+                (1, Some(_), bid) => {
+                    let amount = 100 + next(50) as u128;
+                    if matches!(bid, Some(bid) if bid.price.amount.u128() > amount) {
+                        continue;
+                    }
+                    let bidder = bidders[next(bidders.len() as u64) as usize];
+                    (create_bid(deps.as_mut(), mock_env(), mock_info(bidder, &coins(amount, "uluna")), token.to_string(), NFT.to_string(), uluna(amount), at(expire_at)), amount)
+                }
+                (2, _, Some(bid)) => (cancel_bid(deps.as_mut(), mock_env(), mock_info(bid.bidder.as_str(), &[]), token.to_string(), NFT.to_string()), 0),
+                (3, Some(_), Some(bid)) => (accept_bid(deps.as_mut(), mock_env(), mock_info("seller", &[]), token.to_string(), NFT.to_string(), bid.price), 0),
+                (4, Some(_), _) => (safe_execute_order(deps.as_mut(), mock_env(), mock_info("buyer", &coins(100, "uluna")), token.to_string(), NFT.to_string(), uluna(100)), 100),
+                (5, Some(_), _) => (cancel_order(deps.as_mut(), mock_env(), mock_info("seller", &[]), token.to_string(), NFT.to_string()), 0),
+                (6, _, _) => {
+                    swap_tokens += 1;
+                    let amount = 1 + next(30) as u128;
+                    let offered = vec![NftItemMsg { token_id: format!("s{}", swap_tokens), nft_address: NFT.to_string() }];
+                    deps.querier.with_nft_owner(NFT, &offered[0].token_id, "maker");
+                    let wanted = SwapWantMsg::AnyOf { nft_address: NFT.to_string(), count: 1 };
+                    (create_swap(deps.as_mut(), mock_env(), mock_info("maker", &coins(amount, "uluna")), offered, Some(uluna(amount)), wanted, at(expire_at)), amount)
+                }
+                (7, _, _) => {
+                    let swap_id = match SWAPS.range(&deps.storage, None, None, CwOrder::Ascending).next() {
+                        Some(item) => item.unwrap().1.swap_id,
+                        None => continue
+                    };
+                    (cancel_swap(deps.as_mut(), mock_env(), mock_info("maker", &[]), swap_id), 0)
+                }
+                (8, _, _) => {
+                    let address = ["alice", "seller"][next(2) as usize];
+                    (withdraw(deps.as_mut(), mock_env(), mock_info(address, &[]), None), 0)
+                }
+                (9, _, _) => (claim_fees(deps.as_mut(), mock_env(), mock_info("owner", &[]), None, None), 0),
+                _ => continue
+            };
+            held += Uint128::from(sent);
+            for msg in res.unwrap().messages {
+                if let CosmosMsg::Bank(BankMsg::Send { amount, .. }) = msg.msg {
+                    held = held.checked_sub(amount.iter().filter(|c| c.denom == "uluna").map(|c| c.amount).sum()).unwrap();
+                }
+            }
+            deps.querier.with_contract_balance(&coins(held.u128(), "uluna"));
+
+            let mut owed = Uint128::zero();
+            for item in bids().range(&deps.storage, None, None, CwOrder::Ascending) {
+                owed += item.unwrap().1.price.amount;
+            }
+            for item in SWAPS.range(&deps.storage, None, None, CwOrder::Ascending) {
+                owed += item.unwrap().1.sweetener.map(|s| s.amount).unwrap_or_default();
+            }
+            for item in CLAIMABLE.range(&deps.storage, None, None, CwOrder::Ascending) {
+                owed += item.unwrap().1.amount;
+            }
+            let report: EscrowBalancesResponse = from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::EscrowBalances {}).unwrap()).unwrap();
+            assert!(report.solvent);
+            let (escrowed, fees) = report.balances.iter()
+                .find(|b| b.info == native("uluna"))
+                .map(|b| {
+                    assert_eq!(b.held, held);
+                    (b.escrowed, b.fees)
+                })
+                .unwrap_or_default();
+            assert_eq!(escrowed, owed);
+            assert_eq!(escrowed + fees, held);
+        }
+    }
+
+    #[test]
+    fn pull_payouts_are_credited_and_withdrawn() {
+        let mut deps = mock_dependencies(&[]);
+        do_instantiate(deps.as_mut());
+        let expire_at = mock_env().block.time.seconds() + 3600;
+        set_payout_mode(deps.as_mut(), mock_env(), mock_info("seller", &[]), PayoutMode::Pull).unwrap();
+        set_payout_mode(deps.as_mut(), mock_env(), mock_info("bidder", &[]), PayoutMode::Pull).unwrap();
+        list(deps.as_mut(), "seller", "1", uluna(100));
+        create_bid(deps.as_mut(), mock_env(), mock_info("bidder", &coins(100, "uluna")), "1".to_string(), NFT.to_string(), uluna(100), at(expire_at)).unwrap();
+
+        // the outbid bidder and the seller are credited, only the NFT moves
+        let res = create_bid(deps.as_mut(), mock_env(), mock_info("other", &coins(120, "uluna")), "1".to_string(), NFT.to_string(), uluna(120), at(expire_at)).unwrap();
+        assert!(res.messages.is_empty());
+        let res = accept_bid(deps.as_mut(), mock_env(), mock_info("seller", &[]), "1".to_string(), NFT.to_string(), uluna(120)).unwrap();
+        let msgs: Vec<_> = res.messages.into_iter().map(|m| m.msg).collect();
+        assert_eq!(msgs, vec![nft_transfer(NFT, "1", "other")]);
+        let claimable: ClaimableResponse = from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Claimable { address: "seller".to_string() }).unwrap()).unwrap();
+        assert_eq!(claimable, ClaimableResponse { address: "seller".to_string(), mode: PayoutMode::Pull, balances: vec![uluna(118)] });
+        assert_eq!(ESCROW.load(&deps.storage, b"uluna").unwrap().amount, Uint128::from(218u128));
+
+        let res = withdraw(deps.as_mut(), mock_env(), mock_info("bidder", &[]), Some(vec![native("uusd")])).unwrap();
+        assert!(res.messages.is_empty());
+        let res = withdraw(deps.as_mut(), mock_env(), mock_info("bidder", &[]), None).unwrap();
+        assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send { to_address: "bidder".to_string(), amount: coins(100, "uluna") }));
+        let res = withdraw(deps.as_mut(), mock_env(), mock_info("bidder", &[]), None).unwrap();
+        assert!(res.messages.is_empty());
+        assert_eq!(ESCROW.load(&deps.storage, b"uluna").unwrap().amount, Uint128::from(118u128));
+
+        set_payout_mode(deps.as_mut(), mock_env(), mock_info("seller", &[]), PayoutMode::Push).unwrap();
+        let claimable: ClaimableResponse = from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Claimable { address: "seller".to_string() }).unwrap()).unwrap();
+        assert_eq!(claimable.mode, PayoutMode::Push);
+        assert_eq!(claimable.balances, vec![uluna(118)]);
+    }
+
+    #[test]
+    fn fees_accrue_until_claimed() {
+        let mut deps = mock_dependencies(&[]);
+        do_instantiate(deps.as_mut());
+        deps.querier.with_tax(Decimal::zero(), &[("uusd", 1_000_000)]);
+        list(deps.as_mut(), "seller", "1", uluna(100));
+        list(deps.as_mut(), "seller", "2", uluna(200));
+        list(deps.as_mut(), "seller", "3", Asset { info: native("uusd"), amount: Uint128::from(500u128) });
+        for (token_id, price) in [("1", uluna(100)), ("2", uluna(200))] {
+            let res = safe_execute_order(deps.as_mut(), mock_env(), mock_info("buyer", &coins(price.amount.u128(), "uluna")), token_id.to_string(), NFT.to_string(), price).unwrap();
+            assert_eq!(res.messages.len(), 2);
+        }
+        safe_execute_order(deps.as_mut(), mock_env(), mock_info("buyer", &coins(500, "uusd")), "3".to_string(), NFT.to_string(), Asset { info: native("uusd"), amount: Uint128::from(500u128) }).unwrap();
+
+        let res: AccruedFeesResponse = from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::AccruedFees {}).unwrap()).unwrap();
+        assert_eq!(res.fees, vec![uluna(6), Asset { info: native("uusd"), amount: Uint128::from(10u128) }]);
+
+        let err = claim_fees(deps.as_mut(), mock_env(), mock_info("seller", &[]), None, None).unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        let res = claim_fees(deps.as_mut(), mock_env(), mock_info("owner", &[]), Some("treasury".to_string()), Some(vec![native("uluna")])).unwrap();
+        assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send { to_address: "treasury".to_string(), amount: coins(6, "uluna") }));
+        let res = claim_fees(deps.as_mut(), mock_env(), mock_info("owner", &[]), None, None).unwrap();
+        let msgs: Vec<_> = res.messages.into_iter().map(|m| m.msg).collect();
+        assert_eq!(msgs, vec![CosmosMsg::Bank(BankMsg::Send { to_address: "owner".to_string(), amount: coins(10, "uusd") })]);
+        let res: AccruedFeesResponse = from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::AccruedFees {}).unwrap()).unwrap();
+        assert!(res.fees.is_empty());
+    }
+
+    #[test]
+    fn fee_tiers_and_collection_overrides() {
+        let mut deps = mock_dependencies(&[]);
+        do_instantiate(deps.as_mut());
+        let simulated_rate = |deps: Deps, token_id: &str| -> Decimal {
+            let res = query(deps, mock_env(), QueryMsg::SimulateBuy { token_id: token_id.to_string(), nft_address: NFT.to_string() }).unwrap();
+            from_binary::<SettlementResponse>(&res).unwrap().fee_rate
+        };
+        let tier = FeeTier { asset_info: native("uluna"), min_volume: Uint128::from(1000u128), rate: Decimal::permille(5) };
+
+        let err = set_collection_fee(deps.as_mut(), mock_env(), mock_info("seller", &[]), NFT.to_string(), Some(Decimal::percent(1))).unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        let err = set_collection_fee(deps.as_mut(), mock_env(), mock_info("owner", &[]), NFT.to_string(), Some(Decimal::percent(11))).unwrap_err();
+        assert_eq!(err, ContractError::InvalidFeeRate { max: Decimal::percent(10) });
+        let err = set_fee_tiers(deps.as_mut(), mock_env(), mock_info("owner", &[]), vec![FeeTier { rate: Decimal::percent(11), ..tier.clone() }]).unwrap_err();
+        assert_eq!(err, ContractError::InvalidFeeRate { max: Decimal::percent(10) });
+        set_collection_fee(deps.as_mut(), mock_env(), mock_info("owner", &[]), NFT.to_string(), Some(Decimal::percent(1))).unwrap();
+        set_fee_tiers(deps.as_mut(), mock_env(), mock_info("owner", &[]), vec![tier.clone()]).unwrap();
+        let res: FeeScheduleResponse = from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::FeeSchedule { nft_address: Some(NFT.to_string()) }).unwrap()).unwrap();
+        assert_eq!(res, FeeScheduleResponse {
+            owner_cut_rate: Decimal::percent(2),
+            owner_cut_rate_max: Decimal::percent(10),
+            collection_rate: Some(Decimal::percent(1)),
+            tiers: vec![tier]
+        });
+
+        // the partner collection rate applies until the seller reaches the tier
+        list(deps.as_mut(), "seller", "1", uluna(1000));
+        assert_eq!(simulated_rate(deps.as_ref(), "1"), Decimal::percent(1));
+        safe_execute_order(deps.as_mut(), mock_env(), mock_info("buyer", &coins(1000, "uluna")), "1".to_string(), NFT.to_string(), uluna(1000)).unwrap();
+        assert_eq!(ACCRUED_FEES.load(&deps.storage, b"uluna").unwrap().amount, Uint128::from(10u128));
+        list(deps.as_mut(), "seller", "2", uluna(1000));
+        assert_eq!(simulated_rate(deps.as_ref(), "2"), Decimal::permille(5));
+        safe_execute_order(deps.as_mut(), mock_env(), mock_info("buyer", &coins(1000, "uluna")), "2".to_string(), NFT.to_string(), uluna(1000)).unwrap();
+        assert_eq!(ACCRUED_FEES.load(&deps.storage, b"uluna").unwrap().amount, Uint128::from(15u128));
+
+        set_collection_fee(deps.as_mut(), mock_env(), mock_info("owner", &[]), NFT.to_string(), None).unwrap();
+        list(deps.as_mut(), "seller", "3", uluna(1000));
+        list(deps.as_mut(), "other", "4", uluna(1000));
+        assert_eq!(simulated_rate(deps.as_ref(), "3"), Decimal::permille(5));
+        assert_eq!(simulated_rate(deps.as_ref(), "4"), Decimal::percent(2));
+    }
+
+    #[test]
+    fn payout_swap_is_refused_in_pull_mode() {
+        let mut deps = mock_dependencies(&[]);
+        do_instantiate(deps.as_mut());
+        deps.querier.with_tax(Decimal::zero(), &[]);
+        deps.querier.with_swap_rate("uusd", "ukrw", Decimal::from_ratio(1200u128, 1u128));
+        deps.querier.with_nft_owner(NFT, "2", "seller");
+        let expire_at = mock_env().block.time.seconds() + 3600;
+        let price = Asset { amount: Uint128::from(1000u128), info: native("uusd") };
+        let swap_to_krw = Some(PayoutSwap { denom: "ukrw".to_string(), min_received: Uint128::zero() });
+
+        set_payout_mode(deps.as_mut(), mock_env(), mock_info("seller", &[]), PayoutMode::Pull).unwrap();
+        let err = _create_order(deps.as_mut(), mock_env(), mock_info("seller", &[]), "1".to_string(), NFT.to_string(), price.clone(), at(expire_at), swap_to_krw.clone(), None).unwrap_err();
+        assert_eq!(err, ContractError::PayoutSwapInPullMode {});
+        let item = NftItemMsg { nft_address: NFT.to_string(), token_id: "2".to_string() };
+        let err = create_bundle(deps.as_mut(), mock_env(), mock_info("seller", &[]), vec![item], price.clone(), at(expire_at), swap_to_krw.clone()).unwrap_err();
+        assert_eq!(err, ContractError::PayoutSwapInPullMode {});
+
+        // switching to pull mode after listing blocks the sale instead of dropping the swap
+        set_payout_mode(deps.as_mut(), mock_env(), mock_info("seller", &[]), PayoutMode::Push).unwrap();
+        _create_order(deps.as_mut(), mock_env(), mock_info("seller", &[]), "1".to_string(), NFT.to_string(), price.clone(), at(expire_at), swap_to_krw.clone(), None).unwrap();
+        set_payout_mode(deps.as_mut(), mock_env(), mock_info("seller", &[]), PayoutMode::Pull).unwrap();
+        let err = safe_execute_order(deps.as_mut(), mock_env(), mock_info("buyer", &coins(1000, "uusd")), "1".to_string(), NFT.to_string(), price.clone()).unwrap_err();
+        assert_eq!(err, ContractError::PayoutSwapInPullMode {});
+        let err = _update_order(deps.as_mut(), mock_env(), mock_info("seller", &[]), "1".to_string(), NFT.to_string(), price.clone(), at(expire_at), swap_to_krw, None).unwrap_err();
+        assert_eq!(err, ContractError::PayoutSwapInPullMode {});
+
+        _update_order(deps.as_mut(), mock_env(), mock_info("seller", &[]), "1".to_string(), NFT.to_string(), price.clone(), at(expire_at), None, None).unwrap();
+        safe_execute_order(deps.as_mut(), mock_env(), mock_info("buyer", &coins(1000, "uusd")), "1".to_string(), NFT.to_string(), price).unwrap();
+        assert_eq!(CLAIMABLE.load(&deps.storage, ("seller", b"uusd")).unwrap(), Asset { amount: Uint128::from(980u128), info: native("uusd") });
+    }
+
+    #[test]
+    fn list_orders_pages_through_a_collection() {
+        const OTHER_NFT: &str = "terra1other";
+        let mut deps = mock_dependencies(&[]);
+        do_instantiate(deps.as_mut());
+        let expire_at = mock_env().block.time.seconds() + 3600;
+        list(deps.as_mut(), "seller", "1", uluna(100));
+        _create_order(deps.as_mut(), mock_env(), mock_info("seller", &[]), "2".to_string(), OTHER_NFT.to_string(), uluna(100), at(expire_at), None, None).unwrap();
+        list(deps.as_mut(), "seller", "3", uluna(100));
+
+        let list_orders = |deps: Deps, nft_address: Option<&str>, start_after: Option<&str>| -> Vec<String> {
+            let start_after = start_after.map(|token_id| NftItemMsg { token_id: token_id.to_string(), nft_address: NFT.to_string() });
+            let msg = QueryMsg::ListOrders { nft_address: nft_address.map(|n| n.to_string()), viewer: None, start_after, limit: Some(1) };
+            let res: OrdersResponse = from_binary(&query(deps, mock_env(), msg).unwrap()).unwrap();
+            res.orders.into_iter().map(|o| o.order.token_id).collect()
+        };
+        assert_eq!(list_orders(deps.as_ref(), Some(NFT), None), vec!["1"]);
+        assert_eq!(list_orders(deps.as_ref(), Some(NFT), Some("1")), vec!["3"]);
+        assert!(list_orders(deps.as_ref(), Some(NFT), Some("3")).is_empty());
+        assert_eq!(list_orders(deps.as_ref(), None, Some("1")), vec!["2"]);
+
+        // orders listed before the collection index existed are picked up on migration
+        clear_namespace(&mut deps.storage, b"orders__collection");
+        assert!(list_orders(deps.as_ref(), Some(NFT), None).is_empty());
+        migrate(deps.as_mut(), mock_env(), MigrateMsg::default()).unwrap();
+        assert_eq!(list_orders(deps.as_ref(), Some(OTHER_NFT), None), vec!["2"]);
+        assert_eq!(list_orders(deps.as_ref(), Some(NFT), Some("1")), vec!["3"]);
+    }
+
+    #[test]
+    fn private_listings_are_reserved() {
+        let mut deps = mock_dependencies(&[]);
+        do_instantiate(deps.as_mut());
+        deps.querier.with_nft_owner(NFT, "1", "seller");
+        deps.querier.with_nft_owner(NFT, "2", "seller");
+        let expire_at = mock_env().block.time.seconds() + 3600;
+        create_order(deps.as_mut(), mock_env(), mock_info("seller", &[]), "1".to_string(), NFT.to_string(), uluna(100), at(expire_at), None, Some("friend".to_string())).unwrap();
+        list(deps.as_mut(), "seller", "2", uluna(100));
+
+        let list_orders = |deps: Deps, viewer: Option<&str>| -> Vec<String> {
+            let msg = QueryMsg::ListOrders { nft_address: Some(NFT.to_string()), viewer: viewer.map(|v| v.to_string()), start_after: None, limit: None };
+            let res: OrdersResponse = from_binary(&query(deps, mock_env(), msg).unwrap()).unwrap();
+            res.orders.into_iter().map(|o| o.order.token_id).collect()
+        };
+        assert_eq!(list_orders(deps.as_ref(), None), vec!["2"]);
+        assert_eq!(list_orders(deps.as_ref(), Some("buyer")), vec!["2"]);
+        assert_eq!(list_orders(deps.as_ref(), Some("friend")), vec!["1", "2"]);
+        assert_eq!(list_orders(deps.as_ref(), Some("seller")), vec!["1", "2"]);
+
+        let err = safe_execute_order(deps.as_mut(), mock_env(), mock_info("buyer", &coins(100, "uluna")), "1".to_string(), NFT.to_string(), uluna(100)).unwrap_err();
+        assert_eq!(err, ContractError::ReservedOrder {});
+        let err = create_bid(deps.as_mut(), mock_env(), mock_info("buyer", &coins(150, "uluna")), "1".to_string(), NFT.to_string(), uluna(150), at(expire_at)).unwrap_err();
+        assert_eq!(err, ContractError::ReservedOrder {});
+        let res = sweep_floor(deps.as_mut(), mock_env(), mock_info("buyer", &coins(200, "uluna")), NFT.to_string(), 2, uluna(200)).unwrap();
+        assert!(res.attributes.iter().any(|a| a.key == "count" && a.value == "1"));
+
+        let res = safe_execute_order(deps.as_mut(), mock_env(), mock_info("friend", &coins(100, "uluna")), "1".to_string(), NFT.to_string(), uluna(100)).unwrap();
+        assert_eq!(res.messages[1].msg, nft_transfer(NFT, "1", "friend"));
+    }
+
+    #[test]
+    fn nft_swaps_settle_both_sides() {
+        const OTHER_NFT: &str = "terra1other";
+        let mut deps = mock_dependencies(&[]);
+        do_instantiate(deps.as_mut());
+        deps.querier.with_nft_owner(NFT, "1", "maker");
+        deps.querier.with_nft_owner(NFT, "2", "maker");
+        deps.querier.with_nft_owner(OTHER_NFT, "7", "taker");
+        deps.querier.with_nft_owner(OTHER_NFT, "8", "taker");
+        let expire_at = mock_env().block.time.seconds() + 3600;
+        let item = |nft_address: &str, token_id: &str| NftItemMsg { nft_address: nft_address.to_string(), token_id: token_id.to_string() };
+
+        let err = create_swap(deps.as_mut(), mock_env(), mock_info("maker", &[]), vec![item(NFT, "1")], Some(uluna(50)), SwapWantMsg::Nfts { nfts: vec![item(OTHER_NFT, "7")] }, at(expire_at)).unwrap_err();
+        assert!(matches!(err, ContractError::Std(_)));
+        let res = create_swap(deps.as_mut(), mock_env(), mock_info("maker", &coins(50, "uluna")), vec![item(NFT, "1")], Some(uluna(50)), SwapWantMsg::Nfts { nfts: vec![item(OTHER_NFT, "7")] }, at(expire_at)).unwrap();
+        assert_eq!(res.attributes[1].value, "1");
+        create_swap(deps.as_mut(), mock_env(), mock_info("maker", &[]), vec![item(NFT, "2")], None, SwapWantMsg::AnyOf { nft_address: OTHER_NFT.to_string(), count: 2 }, at(expire_at)).unwrap();
+
+        let err = fill_swap(deps.as_mut(), mock_env(), mock_info("taker", &[]), 1, vec![item(OTHER_NFT, "8")]).unwrap_err();
+        assert_eq!(err, ContractError::InvalidSwap {});
+        let res = fill_swap(deps.as_mut(), mock_env(), mock_info("taker", &[]), 1, vec![item(OTHER_NFT, "7")]).unwrap();
+        let msgs: Vec<_> = res.messages.into_iter().map(|m| m.msg).collect();
+        assert_eq!(msgs, vec![
+            nft_transfer(OTHER_NFT, "7", "maker"),
+            nft_transfer(NFT, "1", "taker"),
+            CosmosMsg::Bank(BankMsg::Send { to_address: "taker".to_string(), amount: coins(50, "uluna") }),
+        ]);
+        let err = fill_swap(deps.as_mut(), mock_env(), mock_info("taker", &[]), 1, vec![item(OTHER_NFT, "7")]).unwrap_err();
+        assert_eq!(err, ContractError::NoSwap {});
+
+        let err = fill_swap(deps.as_mut(), mock_env(), mock_info("taker", &[]), 2, vec![item(OTHER_NFT, "8")]).unwrap_err();
+        assert_eq!(err, ContractError::InvalidSwap {});
+        let err = cancel_swap(deps.as_mut(), mock_env(), mock_info("taker", &[]), 2).unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        let res = cancel_swap(deps.as_mut(), mock_env(), mock_info("maker", &[]), 2).unwrap();
+        assert_eq!(res.messages[0].msg, nft_transfer(NFT, "2", "maker"));
+        assert!(query(deps.as_ref(), mock_env(), QueryMsg::GetSwap { swap_id: 2 }).is_err());
+    }
+
+    #[test]
+    fn counter_offer_answers_a_bid_below_the_ask() {
+        let mut deps = mock_dependencies(&[]);
+        do_instantiate(deps.as_mut());
+        deps.querier.with_nft_owner(NFT, "1", "seller");
+        list(deps.as_mut(), "seller", "1", uluna(200));
+        let expire_at = mock_env().block.time.seconds() + 3600;
+        let token_id = "1".to_string();
+        create_bid(deps.as_mut(), mock_env(), mock_info("bidder", &coins(100, "uluna")), token_id.clone(), NFT.to_string(), uluna(100), at(expire_at)).unwrap();
+
+        let err = counter_offer(deps.as_mut(), mock_env(), mock_info("bidder", &[]), token_id.clone(), NFT.to_string(), "bidder".to_string(), uluna(150), at(expire_at)).unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        let err = counter_offer(deps.as_mut(), mock_env(), mock_info("seller", &[]), token_id.clone(), NFT.to_string(), "other".to_string(), uluna(150), at(expire_at)).unwrap_err();
+        assert_eq!(err, ContractError::NoBid {});
+        // the counter sits above the bid and at most at the asking price
+        for price in [100, 201] {
+            let err = counter_offer(deps.as_mut(), mock_env(), mock_info("seller", &[]), token_id.clone(), NFT.to_string(), "bidder".to_string(), uluna(price), at(expire_at)).unwrap_err();
+            assert_eq!(err, ContractError::InvalidPrice {});
+        }
+        counter_offer(deps.as_mut(), mock_env(), mock_info("seller", &[]), token_id.clone(), NFT.to_string(), "bidder".to_string(), uluna(150), at(expire_at)).unwrap();
+
+        let err = accept_counter_offer(deps.as_mut(), mock_env(), mock_info("other", &coins(50, "uluna")), token_id.clone(), NFT.to_string(), uluna(150)).unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        let err = accept_counter_offer(deps.as_mut(), mock_env(), mock_info("bidder", &coins(40, "uluna")), token_id.clone(), NFT.to_string(), uluna(150)).unwrap_err();
+        assert!(matches!(err, ContractError::Std(_)));
+        let res = accept_counter_offer(deps.as_mut(), mock_env(), mock_info("bidder", &coins(50, "uluna")), token_id.clone(), NFT.to_string(), uluna(150)).unwrap();
+        let msgs: Vec<_> = res.messages.into_iter().map(|m| m.msg).collect();
+        assert_eq!(msgs, vec![
+            CosmosMsg::Bank(BankMsg::Send { to_address: "seller".to_string(), amount: coins(147, "uluna") }),
+            nft_transfer(NFT, "1", "bidder"),
+        ]);
+        assert!(query(deps.as_ref(), mock_env(), QueryMsg::GetCounterOffer { token_id: token_id.clone(), nft_address: NFT.to_string() }).is_err());
+
+        let res: NegotiationResponse = from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::GetNegotiation { token_id, nft_address: NFT.to_string() }).unwrap()).unwrap();
+        let steps: Vec<_> = res.entries.iter().map(|e| (e.action.clone(), e.from.to_string(), e.price.amount.u128())).collect();
+        assert_eq!(steps, vec![
+            (NegotiationAction::Bid, "bidder".to_string(), 100),
+            (NegotiationAction::CounterOffer, "seller".to_string(), 150),
+            (NegotiationAction::Accepted, "seller".to_string(), 150),
+        ]);
+    }
+
+    #[test]
+    fn cleanup_expired_returns_escrow_and_pays_keeper() {
+        let mut deps = mock_dependencies(&[]);
+        do_instantiate(deps.as_mut());
+        for token_id in ["1", "2", "3", "4"] {
+            deps.querier.with_nft_owner(NFT, token_id, "seller");
+        }
+        let now = mock_env().block.time.seconds();
+        list(deps.as_mut(), "seller", "1", uluna(100));
+        _create_order(deps.as_mut(), mock_env(), mock_info("seller", &[]), "2".to_string(), NFT.to_string(), uluna(200), at(now + 120), None, None).unwrap();
+        list(deps.as_mut(), "seller", "3", uluna(300));
+        _create_order(deps.as_mut(), mock_env(), mock_info("seller", &[]), "4".to_string(), NFT.to_string(), uluna(400), at(now + 150), None, None).unwrap();
+        create_bid(deps.as_mut(), mock_env(), mock_info("bidder1", &coins(100, "uluna")), "1".to_string(), NFT.to_string(), uluna(100), at(now + 100)).unwrap();
+        create_bid(deps.as_mut(), mock_env(), mock_info("bidder2", &coins(200, "uluna")), "2".to_string(), NFT.to_string(), uluna(200), at(now + 3000)).unwrap();
+        create_bid(deps.as_mut(), mock_env(), mock_info("bidder3", &coins(300, "uluna")), "4".to_string(), NFT.to_string(), uluna(300), at(now + 110)).unwrap();
+
+        let err = set_keeper_fee(deps.as_mut(), mock_env(), mock_info("keeper", &[]), Decimal::percent(1)).unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        let err = set_keeper_fee(deps.as_mut(), mock_env(), mock_info("owner", &[]), Decimal::percent(2)).unwrap_err();
+        assert_eq!(err, ContractError::InvalidKeeperFee { max: Decimal::percent(1) });
+        set_keeper_fee(deps.as_mut(), mock_env(), mock_info("owner", &[]), Decimal::percent(1)).unwrap();
+
+        let res = cleanup_expired(deps.as_mut(), mock_env(), mock_info("keeper", &[]), None).unwrap();
+        assert!(res.messages.is_empty());
+
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(200);
+        let err = safe_execute_order(deps.as_mut(), env.clone(), mock_info("buyer", &coins(200, "uluna")), "2".to_string(), NFT.to_string(), uluna(200)).unwrap_err();
+        assert_eq!(err, ContractError::Expired {});
+
+        // bidder2's bid still runs, so it isn't charged for the seller's expiry
+        let res = cleanup_expired(deps.as_mut(), env.clone(), mock_info("keeper", &[]), Some(1)).unwrap();
+        let msgs: Vec<_> = res.messages.into_iter().map(|m| m.msg).collect();
+        assert_eq!(msgs, vec![
+            CosmosMsg::Bank(BankMsg::Send { to_address: "bidder2".to_string(), amount: coins(200, "uluna") }),
+            nft_transfer(NFT, "2", "seller"),
+        ]);
+        assert!(orders().may_load(&deps.storage, ("2", NFT)).unwrap().is_none());
+
+        let res = cleanup_expired(deps.as_mut(), env.clone(), mock_info("keeper", &[]), None).unwrap();
+        let msgs: Vec<_> = res.messages.into_iter().map(|m| m.msg).collect();
+        assert_eq!(msgs, vec![
+            CosmosMsg::Bank(BankMsg::Send { to_address: "bidder3".to_string(), amount: coins(297, "uluna") }),
+            nft_transfer(NFT, "4", "seller"),
+            CosmosMsg::Bank(BankMsg::Send { to_address: "bidder1".to_string(), amount: coins(99, "uluna") }),
+            CosmosMsg::Bank(BankMsg::Send { to_address: "keeper".to_string(), amount: coins(4, "uluna") }),
+        ]);
+        assert!(bids().may_load(&deps.storage, ("1", NFT)).unwrap().is_none());
+        assert!(orders().may_load(&deps.storage, ("1", NFT)).unwrap().is_some());
+        assert!(orders().may_load(&deps.storage, ("3", NFT)).unwrap().is_some());
+    }
+
+    #[test]
+    fn listing_durations_bound_expirations() {
+        let mut deps = mock_dependencies(&[]);
+        do_instantiate(deps.as_mut());
+        deps.querier.with_nft_owner(NFT, "1", "seller");
+        let env = mock_env();
+        let now = env.block.time.seconds();
+        let height = env.block.height;
+        let durations = ListingDurations { min_time: 60, max_time: Some(7 * 24 * 3600), min_height: 10, max_height: Some(1000) };
+
+        let err = set_listing_durations(deps.as_mut(), mock_env(), mock_info("seller", &[]), durations.clone()).unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        let err = set_listing_durations(deps.as_mut(), mock_env(), mock_info("owner", &[]), ListingDurations { max_height: Some(5), ..durations.clone() }).unwrap_err();
+        assert_eq!(err, ContractError::InvalidExpiration {});
+        set_listing_durations(deps.as_mut(), mock_env(), mock_info("owner", &[]), durations).unwrap();
+
+        let create = |deps: DepsMut, expire_at: Expiration| _create_order(deps, mock_env(), mock_info("seller", &[]), "1".to_string(), NFT.to_string(), uluna(100), expire_at, None, None);
+        assert_eq!(create(deps.as_mut(), Expiration::Never {}).unwrap_err(), ContractError::InvalidExpiration {});
+        assert_eq!(create(deps.as_mut(), at(now + 30)).unwrap_err(), ContractError::InvalidExpiration {});
+        assert_eq!(create(deps.as_mut(), at(now + 30 * 24 * 3600)).unwrap_err(), ContractError::InvalidExpiration {});
+        assert_eq!(create(deps.as_mut(), Expiration::AtHeight(height + 5)).unwrap_err(), ContractError::InvalidExpiration {});
+        create(deps.as_mut(), Expiration::AtHeight(height + 100)).unwrap();
+
+        let mut env = mock_env();
+        env.block.height += 50;
+        assert!(cleanup_expired(deps.as_mut(), env.clone(), mock_info("keeper", &[]), None).unwrap().messages.is_empty());
+        env.block.height += 50;
+        let err = safe_execute_order(deps.as_mut(), env.clone(), mock_info("buyer", &coins(100, "uluna")), "1".to_string(), NFT.to_string(), uluna(100)).unwrap_err();
+        assert_eq!(err, ContractError::Expired {});
+        let res = cleanup_expired(deps.as_mut(), env, mock_info("keeper", &[]), None).unwrap();
+        assert_eq!(res.messages[0].msg, nft_transfer(NFT, "1", "seller"));
+    }
+
+    #[test]
+    fn migrate_converts_expirations() {
+        let mut deps = mock_dependencies(&[]);
+        do_instantiate(deps.as_mut());
+        // state of a deployment that predates contract versions
+        deps.storage.remove(b"contract_info");
+        let now = mock_env().block.time.seconds();
+        let pk = ("1", NFT).joined_key();
+        LEGACY_ORDERS.save(&mut deps.storage, &pk, &LegacyOrder {
+            token_id: "1".to_string(),
+            nft_address: Addr::unchecked(NFT),
+            seller: Addr::unchecked("seller"),
+            price: uluna(100),
+            expire_at: now + 3600,
+            payout_swap: None,
+            reserved_for: None
+        }).unwrap();
+        LEGACY_BIDS.save(&mut deps.storage, &pk, &LegacyBid {
+            token_id: "1".to_string(),
+            nft_address: Addr::unchecked(NFT),
+            seller: Addr::unchecked("seller"),
+            bidder: Addr::unchecked("bidder"),
+            price: uluna(100),
+            expire_at: now + 600
+        }).unwrap();
+        deps.storage.remove(b"accepted_assets");
+
+        let err = migrate(deps.as_mut(), mock_env(), MigrateMsg::default()).unwrap_err();
+        assert_eq!(err, ContractError::MissingAcceptedAssets {});
+        let res = migrate(deps.as_mut(), mock_env(), MigrateMsg { accepted_assets: Some(vec![native("uluna")]) }).unwrap();
+        assert_eq!(res.attributes[1].value, "1");
+        // running it again leaves migrated entries alone
+        migrate(deps.as_mut(), mock_env(), MigrateMsg::default()).unwrap();
+        assert_eq!(orders().load(&deps.storage, ("1", NFT)).unwrap().expire_at, at(now + 3600));
+        assert_eq!(bids().load(&deps.storage, ("1", NFT)).unwrap().expire_at, at(now + 600));
+
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(1000);
+        let res = cleanup_expired(deps.as_mut(), env.clone(), mock_info("keeper", &[]), None).unwrap();
+        assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send { to_address: "bidder".to_string(), amount: coins(100, "uluna") }));
+        assert!(orders().may_load(&deps.storage, ("1", NFT)).unwrap().is_some());
+        // trading goes on against the migrated whitelist
+        create_bid(deps.as_mut(), env, mock_info("bidder", &coins(120, "uluna")), "1".to_string(), NFT.to_string(), uluna(120), at(now + 3000)).unwrap();
     }
-}
\ No newline at end of file
+}
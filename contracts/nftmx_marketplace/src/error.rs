@@ -1,12 +1,15 @@
-use cosmwasm_std::StdError;
+use cosmwasm_std::{ OverflowError, StdError };
 use thiserror::Error;
-use cosmwasm_std::Uint128;
+use cosmwasm_std::{ Decimal, Uint128 };
 
 #[derive(Error, Debug, PartialEq)]
 pub enum ContractError {
     #[error("{0}")]
     Std(#[from] StdError),
 
+    #[error("{0}")]
+    Overflow(#[from] OverflowError),
+
     #[error("Unauthorized")]
     Unauthorized {},
 
@@ -16,9 +19,6 @@ pub enum ContractError {
     #[error("Given expiration is already expired or order is already expired")]
     Expired {},
 
-    #[error("You must bid higher or equal to {} (min bid amount)", min_bid_amount)]
-    MinPrice { min_bid_amount: Uint128 },  
-
     #[error("no existing order the nft and token id")]
     NoOrder {},
 
@@ -42,4 +42,73 @@ pub enum ContractError {
 
     #[error("Marketplace: the bid expired")]
     BidExpired {},
+
+    #[error("Marketplace: {asset} is not accepted as payment")]
+    AssetNotAccepted { asset: String },
+
+    #[error("Marketplace: {asset} can't be paid in, only native coins are supported")]
+    UnsupportedAsset { asset: String },
+
+    #[error("Marketplace: the migration needs accepted_assets, there is no payment whitelist yet")]
+    MissingAcceptedAssets {},
+
+    #[error("no existing bundle with the given id")]
+    NoBundle {},
+
+    #[error("Marketplace: a bundle needs distinct NFTs that are not listed on their own")]
+    InvalidBundle {},
+
+    #[error("Marketplace: keeper fee can't exceed {max}")]
+    InvalidKeeperFee { max: Decimal },
+
+    #[error("Marketplace: no counter-offer")]
+    NoCounterOffer {},
+
+    #[error("Marketplace: no swap")]
+    NoSwap {},
+
+    #[error("Marketplace: a swap needs distinct, unlisted NFTs on both sides")]
+    InvalidSwap {},
+
+    #[error("Marketplace: royalty rate can't exceed {max}")]
+    InvalidRoyalty { max: Decimal },
+
+    #[error("Marketplace: fee rate above the maximum of {max}")]
+    InvalidFeeRate { max: Decimal },
+
+    #[error("Marketplace: the collection is banned")]
+    CollectionBanned {},
+
+    #[error("Marketplace: only verified collections can be listed")]
+    CollectionNotVerified {},
+
+    #[error("Marketplace: only available in withdrawal mode")]
+    NotInWithdrawalMode {},
+
+    #[error("Marketplace: the token is blocked")]
+    TokenBlocked {},
+
+    #[error("Marketplace: {address} is blocked")]
+    AddressBlocked { address: String },
+
+    #[error("Marketplace: the order is reserved for another buyer")]
+    ReservedOrder {},
+
+    #[error("Marketplace: sent {sent}{denom} but {required}{denom} is required")]
+    InsufficientFunds { denom: String, required: Uint128, sent: Uint128 },
+
+    #[error("Marketplace: payout swap needs a native price and a different native payout denom")]
+    InvalidPayoutSwap {},
+
+    #[error("Marketplace: payout swaps aren't available in pull payout mode")]
+    PayoutSwapInPullMode {},
+
+    #[error("Marketplace: payout swap would return {received}, less than the minimum {min_received}")]
+    PayoutBelowMinimum { min_received: Uint128, received: Uint128 },
+
+    #[error("Marketplace: failed to query owner of {token_id} on {nft_address}: {reason}")]
+    Cw721QueryFailed { nft_address: String, token_id: String, reason: String },
+
+    #[error("Marketplace: failed to compute tax for {denom}: {reason}")]
+    TaxComputationFailed { denom: String, reason: String },
 }
@@ -4,4 +4,7 @@ mod msg;
 mod state;
 mod asset;
 
-pub use msg::{ExecuteMsg, InstantiateMsg, QueryMsg};
+#[cfg(test)]
+mod mock_querier;
+
+pub use msg::{ExecuteMsg, InstantiateMsg, QueryMsg, SettlementResponse, AcceptedAssetsResponse, OrdersResponse, NegotiationResponse, SalesResponse, CollectionStatsResponse, TopCollectionsResponse, TwapResponse, UserStatsResponse, LeaderboardResponse, OrderResponse, PauseStatusResponse, EscrowBalancesResponse, ClaimableResponse, AccruedFeesResponse, FeeScheduleResponse};
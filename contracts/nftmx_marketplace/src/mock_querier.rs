@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+use cosmwasm_std::testing::{MockApi, MockQuerier, MockStorage, MOCK_CONTRACT_ADDR};
+use cosmwasm_std::{
+  from_binary, from_slice, to_binary, Coin, ContractResult, Decimal, OwnedDeps, Querier, QuerierResult,
+  QueryRequest, SystemError, SystemResult, Uint128, WasmQuery
+};
+use cw721::{Cw721QueryMsg, OwnerOfResponse};
+use terra_cosmwasm::{SwapResponse, TaxCapResponse, TaxRateResponse, TerraQuery, TerraQueryWrapper, TerraRoute};
+
+/// mock_dependencies replacement that understands cw721 `OwnerOf` queries,
+/// the terra treasury queries used for tax computation and market swap simulations.
+pub fn mock_dependencies(
+  contract_balance: &[Coin],
+) -> OwnedDeps<MockStorage, MockApi, WasmMockQuerier> {
+  let custom_querier: WasmMockQuerier =
+    WasmMockQuerier::new(MockQuerier::new(&[(MOCK_CONTRACT_ADDR, contract_balance)]));
+
+  OwnedDeps {
+    storage: MockStorage::default(),
+    api: MockApi::default(),
+    querier: custom_querier,
+  }
+}
+
+pub struct WasmMockQuerier {
+  base: MockQuerier<TerraQueryWrapper>,
+  nft_owners: HashMap<(String, String), String>,
+  tax: Option<(Decimal, HashMap<String, Uint128>)>,
+  swap_rates: HashMap<(String, String), Decimal>,
+}
+
+impl Querier for WasmMockQuerier {
+  fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
+    let request: QueryRequest<TerraQueryWrapper> = match from_slice(bin_request) {
+      Ok(v) => v,
+      Err(e) => {
+        return SystemResult::Err(SystemError::InvalidRequest {
+          error: format!("Parsing query request: {}", e),
+          request: bin_request.into(),
+        })
+      }
+    };
+    self.handle_query(&request)
+  }
+}
+
+impl WasmMockQuerier {
+  pub fn new(base: MockQuerier<TerraQueryWrapper>) -> Self {
+    WasmMockQuerier {
+      base,
+      nft_owners: HashMap::new(),
+      tax: None,
+      swap_rates: HashMap::new(),
+    }
+  }
+
+  pub fn with_nft_owner(&mut self, nft_address: &str, token_id: &str, owner: &str) {
+    self.nft_owners.insert((nft_address.to_string(), token_id.to_string()), owner.to_string());
+  }
+
+  pub fn with_tax(&mut self, rate: Decimal, caps: &[(&str, u128)]) {
+    let caps = caps.iter().map(|(denom, cap)| (denom.to_string(), Uint128::from(*cap))).collect();
+    self.tax = Some((rate, caps));
+  }
+
+  pub fn with_contract_balance(&mut self, balance: &[Coin]) {
+    self.base.update_balance(MOCK_CONTRACT_ADDR, balance.to_vec());
+  }
+
+  pub fn with_swap_rate(&mut self, offer_denom: &str, ask_denom: &str, rate: Decimal) {
+    self.swap_rates.insert((offer_denom.to_string(), ask_denom.to_string()), rate);
+  }
+
+  pub fn handle_query(&self, request: &QueryRequest<TerraQueryWrapper>) -> QuerierResult {
+    match request {
+      QueryRequest::Custom(TerraQueryWrapper { route: TerraRoute::Treasury, query_data }) => {
+        let (rate, caps) = match &self.tax {
+          Some(tax) => tax,
+          None => return SystemResult::Err(SystemError::UnsupportedRequest { kind: "treasury".to_string() }),
+        };
+        match query_data {
+          TerraQuery::TaxRate {} => SystemResult::Ok(ContractResult::Ok(
+            to_binary(&TaxRateResponse { rate: *rate }).unwrap()
+          )),
+          TerraQuery::TaxCap { denom } => SystemResult::Ok(ContractResult::Ok(
+            to_binary(&TaxCapResponse { cap: caps.get(denom).copied().unwrap_or_default() }).unwrap()
+          )),
+          _ => SystemResult::Err(SystemError::UnsupportedRequest { kind: "treasury".to_string() }),
+        }
+      }
+      QueryRequest::Custom(TerraQueryWrapper {
+        route: TerraRoute::Market,
+        query_data: TerraQuery::Swap { offer_coin, ask_denom },
+      }) => match self.swap_rates.get(&(offer_coin.denom.clone(), ask_denom.clone())) {
+        Some(rate) => SystemResult::Ok(ContractResult::Ok(
+          to_binary(&SwapResponse { receive: Coin { denom: ask_denom.clone(), amount: offer_coin.amount * *rate } }).unwrap()
+        )),
+        None => SystemResult::Err(SystemError::UnsupportedRequest { kind: "market".to_string() }),
+      },
+      QueryRequest::Wasm(WasmQuery::Smart { contract_addr, msg }) => {
+        match from_binary(msg) {
+          Ok(Cw721QueryMsg::OwnerOf { token_id, .. }) => {
+            match self.nft_owners.get(&(contract_addr.clone(), token_id)) {
+              Some(owner) => SystemResult::Ok(ContractResult::Ok(
+                to_binary(&OwnerOfResponse { owner: owner.clone(), approvals: vec![] }).unwrap()
+              )),
+              None => SystemResult::Err(SystemError::NoSuchContract { addr: contract_addr.clone() }),
+            }
+          }
+          _ => SystemResult::Err(SystemError::UnsupportedRequest { kind: "wasm".to_string() }),
+        }
+      }
+      _ => self.base.handle_query(request),
+    }
+  }
+}
@@ -1,47 +1,321 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use cosmwasm_std::{ Uint128, Decimal };
+use cosmwasm_std::{ Uint128, Decimal, Coin };
+use crate::asset::{Asset, AssetInfo};
+use crate::state::{Operation, PayoutMode, FeeTier, CollectionInfo, CollectionStatus, CollectionVolume, ListingDurations, NegotiationEntry, Order, PayoutSwap, Sale, UserVolume};
 use cw0::Expiration;
-use crate::asset::{Asset};
 
 #[derive(Serialize, Deserialize, JsonSchema)]
 pub struct InstantiateMsg {
     pub name: String,
     pub symbol: String,
     pub decimals: Uint128,
-    pub accepted_token: String,
-    pub owner_cut_rate: Decimal
-}
-
-#[derive(Serialize, Deserialize, JsonSchema)]
-pub struct OrderMsg {
-    pub nft_address: String,
-    pub token_id: String, 
-    pub price: Uint128,
-    pub expire_at: Uint128
+    /// Payment assets accepted for every collection without an override
+    pub accepted_assets: Vec<AssetInfo>,
+    pub owner_cut_rate: Decimal,
+    /// Whether buyers pay the Terra tax on top of the listing price
+    #[serde(default)]
+    pub buyer_pays_tax: bool
 }
 
 #[derive(Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum ExecuteMsg {
-    SetPaused { paused: bool },
-    CreateOrder { token_id:String, nft_address: String, price: Asset, expire_at: u64 },
-    UpdateOrder { token_id:String, nft_address: String, price: Asset, expire_at: u64 },
-    CreateBid { token_id:String, nft_address: String, price: Asset, expire_at: u64 },
+    /// Replace the globally paused operations, an empty list unpauses everything
+    SetPaused { operations: Vec<Operation> },
+    SetCollectionPaused { nft_address: String, operations: Vec<Operation> },
+    SetEmergency { emergency: bool },
+    /// Shut the marketplace down for good or, with `false`, reopen it
+    SetWithdrawalMode { enabled: bool },
+    /// In withdrawal mode, give back the escrow of up to `limit` listings, bids, bundles and swaps, callable by anyone
+    EmergencyWithdraw { limit: Option<u32> },
+    SetKeeperFee { keeper_fee: Decimal },
+    /// Pay the accrued fees of `assets`, or of every asset, to `recipient` or the owner
+    ClaimFees { recipient: Option<String>, assets: Option<Vec<AssetInfo>> },
+    SetListingDurations { durations: ListingDurations },
+    SetCurators { curators: Vec<String> },
+    SetRequireVerified { require_verified: bool },
+    /// Register or update a collection, by the owner or a curator
+    SetCollection { nft_address: String, name: String, creator: String, royalty_rate: Decimal, status: CollectionStatus },
+    RemoveCollection { nft_address: String },
+    SetModerators { moderators: Vec<String> },
+    /// Cancel an order as a moderator, the NFT goes back to the seller or to `recovery_address`
+    ForceCancelOrder { token_id: String, nft_address: String, recovery_address: Option<String>, reason: String },
+    SetTokenBlocked { token_id: String, nft_address: String, blocked: bool },
+    SetAddressBlocked { address: String, blocked: bool },
+    /// Return the escrow of up to `limit` expired orders and bids, callable by anyone
+    CleanupExpired { limit: Option<u32> },
+    UpdateAcceptedAssets { assets: Vec<AssetInfo> },
+    SetCollectionAcceptedAssets { nft_address: String, assets: Option<Vec<AssetInfo>> },
+    /// Override the fee rate of a collection, `None` goes back to the default rate
+    SetCollectionFee { nft_address: String, rate: Option<Decimal> },
+    SetFeeTiers { tiers: Vec<FeeTier> },
+    /// `reserved_for` makes a private listing only that address can buy
+    CreateOrder { token_id:String, nft_address: String, price: Asset, expire_at: Expiration, payout_swap: Option<PayoutSwap>, #[serde(default)] reserved_for: Option<String> },
+    UpdateOrder { token_id:String, nft_address: String, price: Asset, expire_at: Expiration, payout_swap: Option<PayoutSwap>, #[serde(default)] reserved_for: Option<String> },
+    CreateBid { token_id:String, nft_address: String, price: Asset, expire_at: Expiration },
     CancelOrder { token_id:String, nft_address: String },
     CancelBid { token_id:String, nft_address: String },
     SafeExecuteOrder { token_id:String, nft_address: String, price: Asset },
-    AcceptBid { token_id:String, nft_address: String, price: Asset }
+    AcceptBid { token_id:String, nft_address: String, price: Asset },
+    /// Seller answers the standing bid of `bidder` with another price
+    CounterOffer { token_id: String, nft_address: String, bidder: String, price: Asset, expire_at: Expiration },
+    /// Bidder accepts the counter-offer, sending the difference to their escrowed bid
+    AcceptCounterOffer { token_id: String, nft_address: String, price: Asset },
+    CreateBundle { nfts: Vec<NftItemMsg>, price: Asset, expire_at: Expiration, payout_swap: Option<PayoutSwap> },
+    CancelBundle { bundle_id: u64 },
+    BuyBundle { bundle_id: u64, price: Asset },
+    BatchCreateOrders { orders: Vec<OrderMsg>, mode: BatchMode },
+    BatchUpdateOrders { orders: Vec<OrderMsg>, mode: BatchMode },
+    BatchCancelOrders { orders: Vec<NftItemMsg>, mode: BatchMode },
+    BatchBuy { orders: Vec<BuyMsg> },
+    /// Offer NFTs, plus an optional native sweetener sent along, in exchange for `wanted`
+    CreateSwap { offered: Vec<NftItemMsg>, sweetener: Option<Asset>, wanted: SwapWantMsg, expire_at: Expiration },
+    CancelSwap { swap_id: u64 },
+    /// The taker approves the marketplace for `nfts` beforehand, they are transferred to the maker on fill
+    FillSwap { swap_id: u64, nfts: Vec<NftItemMsg> },
+    SweepFloor { nft_address: String, max_count: u32, max_total_price: Asset },
+    /// Choose whether proceeds and refunds are transferred right away or credited
+    SetPayoutMode { mode: PayoutMode },
+    /// Transfer out the claimable balances of `assets`, or of every asset
+    Withdraw { assets: Option<Vec<AssetInfo>> }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BuyMsg {
+    pub token_id: String,
+    pub nft_address: String,
+    pub price: Asset
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OrderMsg {
+    pub token_id: String,
+    pub nft_address: String,
+    pub price: Asset,
+    pub expire_at: Expiration,
+    pub payout_swap: Option<PayoutSwap>,
+    #[serde(default)]
+    pub reserved_for: Option<String>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SwapWantMsg {
+    Nfts { nfts: Vec<NftItemMsg> },
+    AnyOf { nft_address: String, count: u32 }
+}
+
+/// How batch operations treat a failing entry
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchMode {
+    /// the first failure reverts the whole batch
+    AllOrNothing,
+    /// failing entries are skipped and reported in the attributes
+    BestEffort
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct NftItemMsg {
+    pub token_id: String,
+    pub nft_address: String
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum QueryMsg {
+    /// Returns an OrderResponse
     GetOrder { token_id: String, nft_address: String },
     GetBid { token_id: String, nft_address: String },
     GetNftOwner { token_id: String, nft_address: String },
+    SimulateBuy { token_id: String, nft_address: String },
+    SimulateAcceptBid { token_id: String, nft_address: String },
+    GetCounterOffer { token_id: String, nft_address: String },
+    /// Bids, counter-offers and acceptances of a token, oldest first
+    GetNegotiation { token_id: String, nft_address: String },
+    /// Payment assets accepted for the given collection, or the global list
+    AcceptedAssets { nft_address: Option<String> },
+    /// Default fee rate and seller tiers, plus the override of `nft_address` if any
+    FeeSchedule { nft_address: Option<String> },
+    /// Active listings, optionally of one collection. Private listings are only
+    /// included when `viewer` is their seller or the buyer they are reserved for
+    ListOrders { nft_address: Option<String>, viewer: Option<String>, start_after: Option<NftItemMsg>, limit: Option<u32> },
+    GetBundle { bundle_id: u64 },
+    SimulateBuyBundle { bundle_id: u64 },
+    GetSwap { swap_id: u64 },
+    GetSale { sale_id: u64 },
+    /// Sales history, newest first, optionally narrowed down by `filter`
+    Sales { filter: Option<SaleFilter>, start_after: Option<u64>, limit: Option<u32> },
+    GetCollection { nft_address: String },
+    /// Paused operations, globally or also counting the pauses of `nft_address`
+    PauseStatus { nft_address: Option<String> },
+    /// Tracked escrow and fees per asset against what the contract actually holds
+    EscrowBalances {},
+    /// Marketplace fees accrued since they were last claimed
+    AccruedFees {},
+    /// Payout mode and claimable balances of an address
+    Claimable { address: String },
+    GetDelisting { token_id: String, nft_address: String },
+    /// Returns a bool
+    IsTokenBlocked { token_id: String, nft_address: String },
+    /// Returns a bool
+    IsAddressBlocked { address: String },
+    CollectionStats { nft_address: String },
+    /// Collections ranked by their traded volume in `asset_info`, highest first
+    TopCollections { asset_info: AssetInfo, start_after: Option<String>, limit: Option<u32> },
+    UserStats { address: String },
+    /// Addresses ranked by their bought plus sold volume in `asset_info`, highest first
+    Leaderboard { asset_info: AssetInfo, start_after: Option<String>, limit: Option<u32> },
+    /// Time-weighted average sale price per asset over the last `window_seconds`
+    Twap { nft_address: String, window_seconds: u64 },
     Version {}
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
-pub struct MigrateMsg {}
+#[serde(rename_all = "snake_case")]
+pub enum SaleFilter {
+    Collection { nft_address: String },
+    Token { nft_address: String, token_id: String },
+    Buyer { address: String },
+    Seller { address: String }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SalesResponse {
+    pub sales: Vec<Sale>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CollectionStatsResponse {
+    pub nft_address: String,
+    /// traded volume per asset
+    pub volume: Vec<Asset>,
+    pub sales_count: u64,
+    pub last_sale: Option<Asset>,
+    /// cheapest active public listing per accepted asset, missing for an asset
+    /// whose cheapest listings are mostly expired or private
+    pub floor: Vec<Asset>,
+    pub listed_count: u64
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct UserStatsResponse {
+    pub address: String,
+    pub items_bought: u64,
+    pub items_sold: u64,
+    pub volumes: Vec<UserVolume>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LeaderboardResponse {
+    pub entries: Vec<UserVolume>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TwapResponse {
+    pub nft_address: String,
+    pub window_seconds: u64,
+    pub prices: Vec<TwapPrice>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TwapPrice {
+    pub price: Asset,
+    /// part of the window covered by observations, shorter for recently traded assets
+    pub observed_seconds: u64
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TopCollectionsResponse {
+    pub collections: Vec<CollectionVolume>
+}
+
+/// Itemized outcome of a sale, as computed at settlement
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SettlementResponse {
+    pub price: Asset,
+    /// Effective fee rate after the collection override and the seller's tier
+    pub fee_rate: Decimal,
+    pub marketplace_fee: Uint128,
+    pub royalty: Uint128,
+    /// Terra stability tax deducted from the native payouts
+    pub tax: Uint128,
+    pub seller_net: Uint128,
+    /// Estimated proceeds after the market swap, when the listing has a payout denom
+    pub seller_payout: Option<Coin>,
+    /// Amount the buyer has to send, including the tax when the buyer bears it
+    pub buyer_total: Uint128
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FeeScheduleResponse {
+    pub owner_cut_rate: Decimal,
+    pub owner_cut_rate_max: Decimal,
+    pub collection_rate: Option<Decimal>,
+    pub tiers: Vec<FeeTier>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AcceptedAssetsResponse {
+    pub assets: Vec<AssetInfo>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OrdersResponse {
+    pub orders: Vec<OrderResponse>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AccruedFeesResponse {
+    pub fees: Vec<Asset>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ClaimableResponse {
+    pub address: String,
+    pub mode: PayoutMode,
+    pub balances: Vec<Asset>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct EscrowBalancesResponse {
+    pub balances: Vec<EscrowBalance>,
+    /// every asset is covered
+    pub solvent: bool
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct EscrowBalance {
+    pub info: AssetInfo,
+    pub escrowed: Uint128,
+    pub fees: Uint128,
+    /// bank or cw20 balance of the contract
+    pub held: Uint128
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PauseStatusResponse {
+    pub paused: Vec<Operation>,
+    pub emergency: bool
+}
+
+/// An order along with what the registry knows of its collection
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OrderResponse {
+    pub order: Order,
+    pub collection: Option<CollectionInfo>,
+    pub verified: bool
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct NegotiationResponse {
+    pub entries: Vec<NegotiationEntry>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct MigrateMsg {
+    /// Payment whitelist, required when upgrading a deployment that predates it
+    #[serde(default)]
+    pub accepted_assets: Option<Vec<AssetInfo>>
+}
\ No newline at end of file
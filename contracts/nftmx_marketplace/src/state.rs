@@ -1,10 +1,10 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use cosmwasm_std::{ Addr };
-use cw_storage_plus::{ Map, Item };
-use cosmwasm_std::{ Decimal };
+use cw_storage_plus::{ Map, Item, U64Key, U128Key, IndexedMap, IndexList, Index, MultiIndex };
 use cw0::Expiration;
-use crate::asset::{Asset};
+use cosmwasm_std::{ Decimal, Timestamp, Uint128 };
+use crate::asset::{Asset, AssetInfo};
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
 pub struct Order {
@@ -12,7 +12,20 @@ pub struct Order {
     pub nft_address: Addr,
     pub seller: Addr,
     pub price: Asset,
-    pub expire_at: u64
+    pub expire_at: Expiration,
+    #[serde(default)]
+    pub payout_swap: Option<PayoutSwap>,
+    /// Private listing that only this address may buy or bid on
+    #[serde(default)]
+    pub reserved_for: Option<Addr>
+}
+
+/// Seller proceeds are swapped to `denom` at settlement,
+/// the sale fails if the swap would return less than `min_received`
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct PayoutSwap {
+    pub denom: String,
+    pub min_received: Uint128
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
@@ -22,18 +35,559 @@ pub struct Bid {
     pub seller: Addr,
     pub bidder: Addr,
     pub price: Asset,
-    pub expire_at: u64
+    pub expire_at: Expiration
+}
+
+/// Seller's reply to a bid, a price only `bidder` can accept by topping up their bid
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct CounterOffer {
+    pub bidder: Addr,
+    pub price: Asset,
+    pub expire_at: Expiration
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum NegotiationAction {
+    Bid,
+    CounterOffer,
+    Accepted
+}
+
+/// One step of the bid/counter-offer history of a token
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct NegotiationEntry {
+    pub action: NegotiationAction,
+    pub from: Addr,
+    pub price: Asset,
+    pub at: u64
+}
+
+/// A settled sale, kept for provenance and price history
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct Sale {
+    pub sale_id: u64,
+    pub nft_address: Addr,
+    pub token_id: String,
+    pub seller: Addr,
+    pub buyer: Addr,
+    pub price: Asset,
+    pub marketplace_fee: Uint128,
+    pub royalty: Uint128,
+    pub sale_type: SaleType,
+    pub time: u64,
+    pub height: u64
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum SaleType {
+    Order,
+    Bid,
+    CounterOffer,
+    /// one NFT of a bundle, priced at its even share of the bundle
+    Bundle { bundle_id: u64 }
+}
+
+/// Several NFTs listed together and sold as a single unit
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct Bundle {
+    pub bundle_id: u64,
+    pub seller: Addr,
+    pub nfts: Vec<BundleItem>,
+    pub price: Asset,
+    pub expire_at: Expiration,
+    pub payout_swap: Option<PayoutSwap>
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct BundleItem {
+    pub token_id: String,
+    pub nft_address: Addr
+}
+
+/// A maker's NFTs, plus an optional native sweetener, offered for other NFTs
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct Swap {
+    pub swap_id: u64,
+    pub maker: Addr,
+    pub offered: Vec<BundleItem>,
+    pub sweetener: Option<Asset>,
+    pub wanted: SwapWant,
+    pub expire_at: Expiration
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum SwapWant {
+    /// exactly these NFTs
+    Nfts { nfts: Vec<BundleItem> },
+    /// any `count` distinct NFTs of a collection
+    AnyOf { nft_address: Addr, count: u32 }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, PartialEq)]
 pub struct Config {
   pub owner: Addr,
-  pub accepted_token: Addr,
   pub owner_cut_rate: Decimal,
   pub owner_cut_rate_max: Decimal,
+  /// Buyers pay the Terra tax on top of the price on direct purchases,
+  /// otherwise it is deducted from the seller and fee payouts
+  #[serde(default)]
+  pub buyer_pays_tax: bool,
+  /// Bitmap of paused operations, see `Operation`
+  #[serde(default)]
+  pub paused_operations: u8,
+  /// Arms the `Cancelling` pause, escrow can always be withdrawn otherwise
+  #[serde(default)]
+  pub emergency: bool,
+  /// Wind-down: trading is disabled and anyone can send escrow back with `EmergencyWithdraw`
+  #[serde(default)]
+  pub withdrawal_mode: bool,
+  /// Share of a refunded expired bid paid to whoever cleans it up
+  #[serde(default)]
+  pub keeper_fee: Decimal,
+  #[serde(default)]
+  pub listing_durations: ListingDurations,
+  /// Addresses besides the owner allowed to manage the collections registry
+  #[serde(default)]
+  pub curators: Vec<Addr>,
+  /// Only verified collections can be listed
+  #[serde(default)]
+  pub require_verified: bool,
+  /// Addresses besides the owner allowed to force-delist and blocklist
+  #[serde(default)]
+  pub moderators: Vec<Addr>,
+  /// Lower fee rates for sellers with enough sold volume
+  #[serde(default)]
+  pub fee_tiers: Vec<FeeTier>
+}
+
+/// Fee rate for sellers who have sold at least `min_volume` of `asset_info`
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct FeeTier {
+  pub asset_info: AssetInfo,
+  pub min_volume: Uint128,
+  pub rate: Decimal
+}
+
+/// Operations that can be paused globally or per collection
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, JsonSchema, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+  Listing,
+  Bidding,
+  Buying,
+  Accepting,
+  /// cancels and refunds, only paused while the emergency flag is set
+  Cancelling
+}
+
+impl Operation {
+  pub const ALL: [Operation; 5] = [Operation::Listing, Operation::Bidding, Operation::Buying, Operation::Accepting, Operation::Cancelling];
+
+  pub fn bit(self) -> u8 {
+    1 << self as u8
+  }
+
+  pub fn to_bits(operations: &[Operation]) -> u8 {
+    operations.iter().fold(0, |bits, op| bits | op.bit())
+  }
+
+  pub fn from_bits(bits: u8) -> Vec<Operation> {
+    Operation::ALL.iter().copied().filter(|op| bits & op.bit() != 0).collect()
+  }
+}
+
+/// How an address receives its proceeds and refunds
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, JsonSchema, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PayoutMode {
+  /// transferred right away, a failing transfer reverts the whole operation
+  Push,
+  /// credited to a claimable balance, taken out with `Withdraw`
+  Pull
+}
+
+impl Default for PayoutMode {
+  fn default() -> Self {
+    PayoutMode::Push
+  }
+}
+
+pub const PAYOUT_MODES: Map<&str, PayoutMode> = Map::new("payout_modes");
+/// Proceeds and refunds credited to pull-mode addresses, keyed by (address, asset)
+pub const CLAIMABLE: Map<(&str, &[u8]), Asset> = Map::new("claimable");
+
+/// Funds held on behalf of users: bids, swap sweeteners and claimable balances, keyed by asset
+pub const ESCROW: Map<&[u8], Asset> = Map::new("escrow");
+/// Marketplace fees held by the contract until the owner claims them, keyed by asset
+pub const ACCRUED_FEES: Map<&[u8], Asset> = Map::new("accrued_fees");
+
+/// Paused operations bitmap per collection
+pub const COLLECTION_PAUSES: Map<&str, u8> = Map::new("collection_pauses");
+
+/// The config's former single pause flag, read by the migration
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct LegacyPause {
+  #[serde(default)]
   pub paused: bool
 }
 
+pub const LEGACY_PAUSE: Item<LegacyPause> = Item::new("config");
+
+/// How far ahead a listing may expire, in seconds for `AtTime` and blocks for `AtHeight`.
+/// `Never` is only allowed while neither maximum is set.
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct ListingDurations {
+  pub min_time: u64,
+  pub max_time: Option<u64>,
+  pub min_height: u64,
+  pub max_height: Option<u64>
+}
+
+impl Default for ListingDurations {
+  fn default() -> Self {
+    ListingDurations {
+      min_time: 60,
+      max_time: None,
+      min_height: 10,
+      max_height: None
+    }
+  }
+}
+
 pub const CONFIG: Item<Config> = Item::new("config");
-pub const ORDERS: Map<(&str, &str), Order> = Map::new("orders");
-pub const BIDS: Map<(&str, &str), Bid> = Map::new("bids");
+
+/// ((nft_address, price asset), price amount, pk)
+type OrderPriceKey = ((Vec<u8>, Vec<u8>), U128Key, Vec<u8>);
+
+/// (expiration kind, block height or time in nanos, pk)
+type ExpiryKey = (Vec<u8>, U64Key, Vec<u8>);
+
+/// Expiration kinds in the expiry indexes that can ever expire
+pub const EXPIRING_KINDS: [&[u8]; 2] = [b"height", b"time"];
+
+fn expiry_key(expiration: &Expiration, pk: Vec<u8>) -> ExpiryKey {
+    let (kind, at): (&[u8], u64) = match expiration {
+        Expiration::AtHeight(height) => (b"height", *height),
+        Expiration::AtTime(time) => (b"time", time.nanos()),
+        Expiration::Never {} => (b"never", 0)
+    };
+    (kind.to_vec(), U64Key::new(at), pk)
+}
+
+pub struct OrderIndexes<'a> {
+    /// orders of a collection, in primary key order
+    pub collection: MultiIndex<'a, (Vec<u8>, Vec<u8>), Order>,
+    /// orders of a collection priced in a given asset, cheapest first
+    pub price: MultiIndex<'a, OrderPriceKey, Order>,
+    /// orders by expiry, soonest first within each kind of expiration
+    pub expiry: MultiIndex<'a, ExpiryKey, Order>,
+}
+
+impl<'a> IndexList<Order> for OrderIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Order>> + '_> {
+        let v: Vec<&dyn Index<Order>> = vec![&self.collection, &self.price, &self.expiry];
+        Box::new(v.into_iter())
+    }
+}
+
+/// Orders keyed by (token_id, nft_address)
+pub fn orders<'a>() -> IndexedMap<'a, (&'a str, &'a str), Order, OrderIndexes<'a>> {
+    let indexes = OrderIndexes {
+        collection: MultiIndex::new(
+            |o: &Order, pk: Vec<u8>| (o.nft_address.as_bytes().to_vec(), pk),
+            "orders",
+            "orders__collection",
+        ),
+        price: MultiIndex::new(
+            |o: &Order, pk: Vec<u8>| (
+                (o.nft_address.as_bytes().to_vec(), o.price.info.as_bytes().to_vec()),
+                U128Key::new(o.price.amount.u128()),
+                pk
+            ),
+            "orders",
+            "orders__price",
+        ),
+        expiry: MultiIndex::new(
+            |o: &Order, pk: Vec<u8>| expiry_key(&o.expire_at, pk),
+            "orders",
+            "orders__expiration",
+        ),
+    };
+    IndexedMap::new("orders", indexes)
+}
+
+pub struct BidIndexes<'a> {
+    /// bids by expiry, soonest first within each kind of expiration
+    pub expiry: MultiIndex<'a, ExpiryKey, Bid>,
+}
+
+impl<'a> IndexList<Bid> for BidIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Bid>> + '_> {
+        let v: Vec<&dyn Index<Bid>> = vec![&self.expiry];
+        Box::new(v.into_iter())
+    }
+}
+
+/// Bids keyed by (token_id, nft_address), one standing bid per token
+pub fn bids<'a>() -> IndexedMap<'a, (&'a str, &'a str), Bid, BidIndexes<'a>> {
+    let indexes = BidIndexes {
+        expiry: MultiIndex::new(
+            |b: &Bid, pk: Vec<u8>| expiry_key(&b.expire_at, pk),
+            "bids",
+            "bids__expiration",
+        ),
+    };
+    IndexedMap::new("bids", indexes)
+}
+pub const COUNTER_OFFERS: Map<(&str, &str), CounterOffer> = Map::new("counter_offers");
+pub const NEGOTIATIONS: Map<(&str, &str), Vec<NegotiationEntry>> = Map::new("negotiations");
+pub const BUNDLES: Map<U64Key, Bundle> = Map::new("bundles");
+pub const BUNDLE_COUNT: Item<u64> = Item::new("bundle_count");
+pub const SWAPS: Map<U64Key, Swap> = Map::new("swaps");
+pub const SWAP_COUNT: Item<u64> = Item::new("swap_count");
+/// ((nft_address, token_id), pk)
+type SaleTokenKey = ((Vec<u8>, Vec<u8>), Vec<u8>);
+
+pub struct SaleIndexes<'a> {
+    pub collection: MultiIndex<'a, (Vec<u8>, Vec<u8>), Sale>,
+    pub token: MultiIndex<'a, SaleTokenKey, Sale>,
+    pub buyer: MultiIndex<'a, (Vec<u8>, Vec<u8>), Sale>,
+    pub seller: MultiIndex<'a, (Vec<u8>, Vec<u8>), Sale>,
+}
+
+impl<'a> IndexList<Sale> for SaleIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Sale>> + '_> {
+        let v: Vec<&dyn Index<Sale>> = vec![&self.collection, &self.token, &self.buyer, &self.seller];
+        Box::new(v.into_iter())
+    }
+}
+
+/// Sales keyed by their sequence number
+pub fn sales<'a>() -> IndexedMap<'a, U64Key, Sale, SaleIndexes<'a>> {
+    let indexes = SaleIndexes {
+        collection: MultiIndex::new(
+            |s: &Sale, pk: Vec<u8>| (s.nft_address.as_bytes().to_vec(), pk),
+            "sales",
+            "sales__collection",
+        ),
+        token: MultiIndex::new(
+            |s: &Sale, pk: Vec<u8>| ((s.nft_address.as_bytes().to_vec(), s.token_id.as_bytes().to_vec()), pk),
+            "sales",
+            "sales__token",
+        ),
+        buyer: MultiIndex::new(
+            |s: &Sale, pk: Vec<u8>| (s.buyer.as_bytes().to_vec(), pk),
+            "sales",
+            "sales__buyer",
+        ),
+        seller: MultiIndex::new(
+            |s: &Sale, pk: Vec<u8>| (s.seller.as_bytes().to_vec(), pk),
+            "sales",
+            "sales__seller",
+        ),
+    };
+    IndexedMap::new("sales", indexes)
+}
+
+pub const SALE_COUNT: Item<u64> = Item::new("sale_count");
+
+/// Registry entry of a collection, managed by the owner and curators
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct CollectionInfo {
+    pub name: String,
+    pub creator: Addr,
+    /// share of every sale paid to `creator`
+    pub royalty_rate: Decimal,
+    pub status: CollectionStatus
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum CollectionStatus {
+    Unverified,
+    Verified,
+    /// can't be listed at all
+    Banned
+}
+
+pub const COLLECTIONS: Map<&str, CollectionInfo> = Map::new("collections");
+
+/// Record of an order cancelled by a moderator
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct Delisting {
+    pub seller: Addr,
+    /// where the NFT went, the seller unless a recovery address was given
+    pub recipient: Addr,
+    pub moderator: Addr,
+    pub reason: String,
+    pub at: u64
+}
+
+pub const DELISTINGS: Map<(&str, &str), Delisting> = Map::new("delistings");
+/// Tokens that can't be listed, keyed by (token_id, nft_address)
+pub const BLOCKED_TOKENS: Map<(&str, &str), bool> = Map::new("blocked_tokens");
+/// Addresses that can't trade
+pub const BLOCKED_ADDRESSES: Map<&str, bool> = Map::new("blocked_addresses");
+
+/// Running totals of a collection, kept up to date as listings and sales happen
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug, Default)]
+pub struct CollectionStats {
+    pub sales_count: u64,
+    pub listed_count: u64,
+    pub last_sale: Option<Asset>
+}
+
+pub const COLLECTION_STATS: Map<&str, CollectionStats> = Map::new("collection_stats");
+
+/// Running trade counts of an address
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug, Default)]
+pub struct UserStats {
+    pub items_bought: u64,
+    pub items_sold: u64
+}
+
+pub const USER_STATS: Map<&str, UserStats> = Map::new("user_stats");
+
+/// Trading amounts of an address in one asset, fees and royalties are what came out of its sales
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct UserVolume {
+    pub address: Addr,
+    pub info: AssetInfo,
+    pub bought: Uint128,
+    pub sold: Uint128,
+    pub fees_paid: Uint128,
+    pub royalties_paid: Uint128
+}
+
+pub struct UserVolumeIndexes<'a> {
+    /// addresses by bought plus sold volume within each asset
+    pub volume: MultiIndex<'a, VolumeKey, UserVolume>,
+}
+
+impl<'a> IndexList<UserVolume> for UserVolumeIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<UserVolume>> + '_> {
+        let v: Vec<&dyn Index<UserVolume>> = vec![&self.volume];
+        Box::new(v.into_iter())
+    }
+}
+
+/// Volumes keyed by (address, asset)
+pub fn user_volumes<'a>() -> IndexedMap<'a, (&'a [u8], &'a [u8]), UserVolume, UserVolumeIndexes<'a>> {
+    let indexes = UserVolumeIndexes {
+        volume: MultiIndex::new(
+            |v: &UserVolume, pk: Vec<u8>| (v.info.as_bytes().to_vec(), U128Key::new(v.bought.u128().saturating_add(v.sold.u128())), pk),
+            "user_volumes",
+            "user_volumes__volume",
+        ),
+    };
+    IndexedMap::new("user_volumes", indexes)
+}
+
+/// A point of a collection's price curve in one asset, see `record_price_observation`
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct PriceObservation {
+    pub time: u64,
+    /// sale price after outlier clamping, held until the next observation
+    pub price: Uint128,
+    /// sum of price * seconds held, up to `time`
+    pub cumulative: Uint128
+}
+
+/// Observations keyed by (nft_address, asset, time)
+pub const PRICE_OBSERVATIONS: Map<(&[u8], &[u8], U64Key), PriceObservation> = Map::new("price_observations");
+
+/// Traded volume of a collection in one asset
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct CollectionVolume {
+    pub nft_address: Addr,
+    pub volume: Asset
+}
+
+/// (asset, volume, pk)
+type VolumeKey = (Vec<u8>, U128Key, Vec<u8>);
+
+pub struct VolumeIndexes<'a> {
+    /// collections by volume within each asset
+    pub volume: MultiIndex<'a, VolumeKey, CollectionVolume>,
+}
+
+impl<'a> IndexList<CollectionVolume> for VolumeIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<CollectionVolume>> + '_> {
+        let v: Vec<&dyn Index<CollectionVolume>> = vec![&self.volume];
+        Box::new(v.into_iter())
+    }
+}
+
+/// Volumes keyed by (nft_address, asset)
+pub fn collection_volumes<'a>() -> IndexedMap<'a, (&'a [u8], &'a [u8]), CollectionVolume, VolumeIndexes<'a>> {
+    let indexes = VolumeIndexes {
+        volume: MultiIndex::new(
+            |v: &CollectionVolume, pk: Vec<u8>| (v.volume.info.as_bytes().to_vec(), U128Key::new(v.volume.amount.u128()), pk),
+            "collection_volumes",
+            "collection_volumes__volume",
+        ),
+    };
+    IndexedMap::new("collection_volumes", indexes)
+}
+pub const ACCEPTED_ASSETS: Item<Vec<AssetInfo>> = Item::new("accepted_assets");
+pub const COLLECTION_ACCEPTED_ASSETS: Map<&str, Vec<AssetInfo>> = Map::new("collection_accepted_assets");
+/// Negotiated fee rates replacing `owner_cut_rate` for a collection
+pub const COLLECTION_FEES: Map<&str, Decimal> = Map::new("collection_fees");
+
+/// Order layout before expirations were `cw0::Expiration`, read by the migration
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct LegacyOrder {
+    pub token_id: String,
+    pub nft_address: Addr,
+    pub seller: Addr,
+    pub price: Asset,
+    pub expire_at: u64,
+    #[serde(default)]
+    pub payout_swap: Option<PayoutSwap>,
+    #[serde(default)]
+    pub reserved_for: Option<Addr>
+}
+
+/// Bid layout before expirations were `cw0::Expiration`, read by the migration
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct LegacyBid {
+    pub token_id: String,
+    pub nft_address: Addr,
+    pub seller: Addr,
+    pub bidder: Addr,
+    pub price: Asset,
+    pub expire_at: u64
+}
+
+impl From<LegacyOrder> for Order {
+    fn from(order: LegacyOrder) -> Self {
+        Order {
+            token_id: order.token_id,
+            nft_address: order.nft_address,
+            seller: order.seller,
+            price: order.price,
+            expire_at: Expiration::AtTime(Timestamp::from_seconds(order.expire_at)),
+            payout_swap: order.payout_swap,
+            reserved_for: order.reserved_for
+        }
+    }
+}
+
+impl From<LegacyBid> for Bid {
+    fn from(bid: LegacyBid) -> Self {
+        Bid {
+            token_id: bid.token_id,
+            nft_address: bid.nft_address,
+            seller: bid.seller,
+            bidder: bid.bidder,
+            price: bid.price,
+            expire_at: Expiration::AtTime(Timestamp::from_seconds(bid.expire_at))
+        }
+    }
+}
+
+pub const LEGACY_ORDERS: Map<&[u8], LegacyOrder> = Map::new("orders");
+pub const LEGACY_BIDS: Map<&[u8], LegacyBid> = Map::new("bids");
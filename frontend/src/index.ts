@@ -26,7 +26,8 @@ import fetch from 'isomorphic-fetch';
 		let timeStamp = Math.floor(Date.now() / 1000 ) + 3600 * 24 * 7
 		console.log('timeStamp', timeStamp)
 		const expire_at = {
-				"at_time": timeStamp.toString(),
+				// cw0 Expiration, the timestamp is in nanoseconds
+				"at_time": timeStamp.toString() + "000000000",
 				// "never": {}
 		},
 		price = {
@@ -43,8 +44,8 @@ import fetch from 'isomorphic-fetch';
 		}
 
 
-		const setPausedMsg = {set_paused: {paused: false}}
-		const createOrderMsg = { create_order: { token_id: "2", nft_address: "terra1rmw87h769rt553myzcvnqavvnqzqxm2r9twsju", price, expire_at: timeStamp } }
+		const setPausedMsg = {set_paused: {operations: []}}
+		const createOrderMsg = { create_order: { token_id: "2", nft_address: "terra1rmw87h769rt553myzcvnqavvnqzqxm2r9twsju", price, expire_at } }
 
 		// Transfer NFT to smart contract address on createOrder
 		//     transfer nft to market place contracts
@@ -55,9 +56,9 @@ import fetch from 'isomorphic-fetch';
 		// 	transferNftMsg
     // )
 
-		const updateOrderMsg = { update_order: { token_id: "2", nft_address: "terra1rmw87h769rt553myzcvnqavvnqzqxm2r9twsju", price, expire_at: timeStamp } }
+		const updateOrderMsg = { update_order: { token_id: "2", nft_address: "terra1rmw87h769rt553myzcvnqavvnqzqxm2r9twsju", price, expire_at } }
 	
-		const createBidMsg = { create_bid: { token_id: "2", nft_address: "terra1rmw87h769rt553myzcvnqavvnqzqxm2r9twsju", price, expire_at: timeStamp } }
+		const createBidMsg = { create_bid: { token_id: "2", nft_address: "terra1rmw87h769rt553myzcvnqavvnqzqxm2r9twsju", price, expire_at } }
 		const createBidCoin = new Coin("uluna", 10000)
 
 		const cancelBidMsg = { cancel_bid: { token_id: "2", nft_address: "terra1rmw87h769rt553myzcvnqavvnqzqxm2r9twsju" } }